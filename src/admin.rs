@@ -0,0 +1,87 @@
+use axum::{
+    extract::{Path, RawQuery},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use serde_json::json;
+
+use crate::journal::{Journal, JournalFilter};
+use crate::metrics::Metrics;
+use crate::scenarios::Scenarios;
+
+/// All admin endpoints live below this prefix so that they don't clash with routes to fake
+pub const ADMIN_PREFIX: &str = "/__dummyhttp";
+
+/// The admin API used by test harnesses to inspect what dummyhttp has received
+///
+/// GET /requests - list recorded requests, optionally filtered by `method`, `path` and
+///                 `header` (format: key:value or just key, may be repeated)
+/// DELETE /requests - clear all recorded requests
+/// GET /requests/count - number of recorded requests matching the same filters
+/// GET /scenarios - current state of all scenarios
+/// DELETE /scenarios - reset all scenarios to their initial state
+/// DELETE /scenarios/:name - reset a single scenario
+/// GET /metrics - Prometheus metrics
+pub fn router() -> Router {
+    Router::new()
+        .route("/requests", get(list_requests).delete(clear_requests))
+        .route("/requests/count", get(count_requests))
+        .route("/scenarios", get(list_scenarios).delete(reset_scenarios))
+        .route("/scenarios/:name", delete(reset_scenario))
+        .route("/metrics", get(metrics))
+}
+
+async fn list_requests(
+    RawQuery(query): RawQuery,
+    Extension(journal): Extension<Journal>,
+) -> Response {
+    match JournalFilter::from_query(query.as_deref().unwrap_or("")) {
+        Ok(filter) => Json(journal.list(&filter)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn count_requests(
+    RawQuery(query): RawQuery,
+    Extension(journal): Extension<Journal>,
+) -> Response {
+    match JournalFilter::from_query(query.as_deref().unwrap_or("")) {
+        Ok(filter) => Json(json!({ "count": journal.list(&filter).len() })).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn clear_requests(Extension(journal): Extension<Journal>) -> StatusCode {
+    journal.clear();
+    StatusCode::NO_CONTENT
+}
+
+async fn list_scenarios(Extension(scenarios): Extension<Scenarios>) -> Response {
+    Json(scenarios.states()).into_response()
+}
+
+async fn reset_scenarios(Extension(scenarios): Extension<Scenarios>) -> StatusCode {
+    scenarios.reset_all();
+    StatusCode::NO_CONTENT
+}
+
+async fn reset_scenario(
+    Path(name): Path<String>,
+    Extension(scenarios): Extension<Scenarios>,
+) -> Response {
+    if scenarios.reset(&name) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Unknown scenario '{}'", name),
+        )
+            .into_response()
+    }
+}
+
+pub async fn metrics(Extension(metrics): Extension<Metrics>) -> impl IntoResponse {
+    ([(CONTENT_TYPE, prometheus::TEXT_FORMAT)], metrics.render())
+}
@@ -1,34 +1,50 @@
-use clap::Parser;
+use clap::{Parser, ValueHint};
+
+use crate::chaos::Fault;
+use crate::compression::Compression;
+use crate::delay::{parse_duration, Delay};
+use crate::listen::{self, parse_mode, Listen, ListenAddr, UnixOwner};
+use crate::logging::LogFormat;
+use crate::ready::AddressFormat;
+use crate::streaming::Bandwidth;
 #[cfg(feature = "tls")]
-use clap::ValueHint;
+use crate::tls::ClientAuth;
 use hyper::header::{HeaderMap, HeaderName, HeaderValue};
-use std::net::IpAddr;
-#[cfg(feature = "tls")]
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Parser)]
 #[clap(name = "dummyhttp", author, about, version)]
 pub struct Args {
     /// Be quiet (log nothing)
     #[clap(short, long)]
-    pub quiet: bool,
+    pub(crate) quiet: bool,
 
     /// Be verbose (log data of incoming and outgoing requests). If given twice it will also log
     /// the body data.
     #[clap(short, long, parse(from_occurrences))]
-    pub verbose: u8,
+    pub(crate) verbose: u8,
+
+    /// Log format
+    ///
+    /// json and logfmt print one record per request including the request and response headers.
+    /// Bodies are included if --verbose is given twice.
+    #[clap(long, arg_enum, default_value = "pretty")]
+    pub(crate) log_format: LogFormat,
 
-    /// Port on which to listen
+    /// Port on which to listen (0 picks a free port)
     #[clap(short, long, default_value = "8080")]
-    pub port: u16,
+    pub(crate) port: u16,
 
     /// Headers to send (format: key:value)
     #[clap(short = 'H', long, parse(try_from_str = parse_header))]
-    pub headers: Vec<HeaderMap>,
+    pub(crate) headers: Vec<HeaderMap>,
 
     /// HTTP status code to send
     #[clap(short, long, default_value = "200")]
-    pub code: u16,
+    pub(crate) code: u16,
 
     /// HTTP body to send
     ///
@@ -38,9 +54,232 @@ pub struct Args {
     /// uuid() - generate a random UUID
     /// lorem(words) - generate `words` lorem ipsum words
     ///
+    /// The incoming request is available as `request` with the fields method, path, query,
+    /// headers, body, json (the body parsed as JSON), peer and params (path captures).
+    ///
     /// Example: dummyhttp -b "Hello {{ uuid() }}, it's {{ now() | date(format="%Y") }} {{ lorem(words=5)}}"
+    /// Example: dummyhttp -b '{"echo": "{{ request.json.id }}"}'
     #[clap(short, long, default_value = "dummyhttp", verbatim_doc_comment)]
-    pub body: String,
+    pub(crate) body: String,
+
+    /// File to send as HTTP body instead of --body
+    ///
+    /// The file is sent as is unless --render-body-file is given. Its Content-Type is guessed from
+    /// the file extension unless given via --headers.
+    #[clap(long, conflicts_with = "body", value_hint = ValueHint::FilePath)]
+    pub(crate) body_file: Option<PathBuf>,
+
+    /// Render --body-file as a Tera template (see --body)
+    #[clap(long, requires = "body-file")]
+    pub(crate) render_body_file: bool,
+
+    /// Serve files from this directory
+    ///
+    /// Request paths are mapped to files in the directory, directories are answered with their
+    /// index.html. Requests for files that don't exist get the default response.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub(crate) serve_dir: Option<PathBuf>,
+
+    /// Delay before sending the response
+    ///
+    /// Supported formats:
+    ///
+    /// 500ms - always wait 500ms
+    /// 100ms..300ms - wait a uniformly distributed time between 100ms and 300ms
+    /// normal:200ms,50ms - normal distribution with a mean of 200ms and a standard deviation of 50ms
+    /// lognormal:200ms,50ms - log-normal distribution with a mean of 200ms and a standard deviation of 50ms
+    ///
+    /// Durations are given as a number followed by one of the units us, ms, s or m.
+    #[clap(long, verbatim_doc_comment)]
+    pub(crate) delay: Option<Delay>,
+
+    /// Send the body slowly, spreading it over the given duration (same format as --delay)
+    ///
+    /// This is applied after --delay, so both can be combined to simulate a slow time to first
+    /// byte followed by a slow transfer.
+    #[clap(long)]
+    pub(crate) trickle: Option<Delay>,
+
+    /// Send the body with chunked transfer encoding in chunks of this many bytes
+    #[clap(long, value_name = "BYTES", conflicts_with_all = &["trickle", "bandwidth"])]
+    pub(crate) chunk_size: Option<usize>,
+
+    /// Wait this long between chunks (same format as --delay)
+    ///
+    /// Chunks are 1024 bytes unless --chunk-size is given.
+    #[clap(long, conflicts_with_all = &["trickle", "bandwidth"])]
+    pub(crate) chunk_delay: Option<Delay>,
+
+    /// Send the body no faster than this many bytes per second
+    ///
+    /// The rate may be suffixed with k or m for KiB/s or MiB/s (e.g. 64k).
+    #[clap(long, conflicts_with = "trickle")]
+    pub(crate) bandwidth: Option<Bandwidth>,
+
+    /// Compress response bodies
+    ///
+    /// off - never compress
+    /// auto - pick gzip, deflate, br or zstd based on the request's Accept-Encoding header
+    /// gzip, deflate, br or zstd - always use this encoding, even if the client doesn't accept it
+    #[clap(long, default_value = "off", verbatim_doc_comment)]
+    pub(crate) compression: Compression,
+
+    /// Send this Content-Encoding header no matter how the body is actually encoded
+    ///
+    /// Useful to test how clients cope with bodies that fail to decode.
+    #[clap(long, value_name = "ENCODING")]
+    pub(crate) fake_content_encoding: Option<HeaderValue>,
+
+    /// Inject a fault into responses with the given probability (can be given multiple times)
+    ///
+    /// Format: <kind>[@<probability>] where kind is one of
+    ///
+    /// status=<code> - answer with a different status code
+    /// reset - reset the TCP connection in the middle of the response
+    /// close - close the connection without responding
+    /// truncate - send only half of the body while announcing the full Content-Length
+    /// malformed - send garbage instead of a valid HTTP response
+    ///
+    /// The probability is a number between 0 and 1 and defaults to 1. At most one fault is
+    /// injected per response, so the probabilities may add up to 1 at most.
+    ///
+    /// Example: dummyhttp --fault status=503@0.2 --fault reset@0.05
+    #[clap(long = "fault", value_name = "FAULT", verbatim_doc_comment)]
+    pub(crate) faults: Vec<Fault>,
+
+    /// Route configuration file (YAML, TOML or JSON)
+    ///
+    /// The file contains a list of routes which are matched in order. Each route may specify a
+    /// method (or a list of methods), a path pattern (e.g. `/users/:id` or `/static/*rest`) as
+    /// well as the code, headers and body to send. Requests not matching any route are answered
+    /// with the response given by --code, --headers and --body.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub(crate) config: Option<PathBuf>,
+
+    /// Serve all operations of an OpenAPI 3 document (YAML or JSON)
+    ///
+    /// Operations answer with their lowest documented 2xx response using the example given in
+    /// the document or one made up from the schema. Other documented responses can be requested
+    /// by sending `Prefer: code=<status>`. Routes from --config take precedence.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub(crate) openapi: Option<PathBuf>,
+
+    /// Validate requests against the --openapi document and answer invalid ones with 400
+    #[clap(long, requires = "openapi")]
+    pub(crate) openapi_validate: bool,
+
+    /// Number of requests to keep in the request journal
+    ///
+    /// Recorded requests can be inspected via GET /__dummyhttp/requests (filterable by method,
+    /// path and header), counted via GET /__dummyhttp/requests/count and cleared via
+    /// DELETE /__dummyhttp/requests. Set to 0 to disable recording.
+    #[clap(long, default_value = "1000")]
+    pub(crate) journal_size: usize,
+
+    /// Also serve Prometheus metrics at /metrics on this port
+    ///
+    /// Metrics are always available at GET /__dummyhttp/metrics on the main port.
+    #[clap(long)]
+    pub(crate) metrics_port: Option<u16>,
+
+    /// Forward requests not matching any route to this upstream (e.g. https://staging.example.com)
+    #[clap(long, value_name = "URL", parse(try_from_str = parse_upstream))]
+    pub(crate) proxy_to: Option<String>,
+
+    /// Record requests forwarded by --proxy-to and their responses to this file
+    ///
+    /// Recordings are appended to the file as one JSON object per line and can be served later
+    /// on using --replay.
+    #[clap(long, requires = "proxy-to", value_hint = ValueHint::FilePath)]
+    pub(crate) record: Option<PathBuf>,
+
+    /// Serve responses recorded with --record
+    ///
+    /// Requests are matched by method, path, query and body. Requests which were recorded
+    /// multiple times get the recorded responses in order. Requests not found in the recording
+    /// are forwarded to --proxy-to if given or get the default response otherwise.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub(crate) replay: Option<PathBuf>,
+
+    /// Listen on this address instead of --interface and --port (can be given multiple times)
+    ///
+    /// Format: <address>[+tls][,config=<file>]
+    ///
+    /// 0.0.0.0:8080 - plain HTTP on all IPv4 interfaces
+    /// [::]:8443+tls - HTTPS on all IPv6 interfaces (needs --tls-cert or --tls-self-signed)
+    /// unix:/tmp/dummyhttp.sock - plain HTTP on a Unix socket
+    /// fd:3+tls - HTTPS on an inherited listening socket
+    /// 127.0.0.1:9090,config=admin.yaml - serve the routes of admin.yaml instead of --config
+    ///
+    /// Example: dummyhttp --tls-self-signed --listen 0.0.0.0:8080 --listen 0.0.0.0:8443+tls
+    #[clap(
+        long,
+        value_name = "ADDRESS",
+        conflicts_with_all = &["port", "interface"],
+        verbatim_doc_comment
+    )]
+    pub(crate) listen: Vec<Listen>,
+
+    /// Listen on a Unix socket at this path instead of --interface and --port
+    ///
+    /// A socket left behind at the path by a previous run is replaced. The peer credentials
+    /// (pid, uid and gid) of clients are logged instead of their address.
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = &["port", "interface"],
+        value_hint = ValueHint::FilePath
+    )]
+    pub(crate) unix_socket: Option<PathBuf>,
+
+    /// Permissions of Unix sockets in octal (e.g. 660)
+    #[clap(long, value_name = "MODE", parse(try_from_str = parse_mode))]
+    pub(crate) unix_socket_mode: Option<u32>,
+
+    /// Owner of Unix sockets (format: <uid>[:<gid>])
+    #[clap(long, value_name = "OWNER")]
+    pub(crate) unix_socket_owner: Option<UnixOwner>,
+
+    /// Serve on an inherited listening socket instead of binding --interface and --port
+    ///
+    /// The socket can be a TCP or Unix socket bound by the parent process, which avoids racing
+    /// for a port. It speaks HTTPS if a TLS certificate is configured. Sockets passed via systemd
+    /// socket activation (LISTEN_FDS) are used automatically if no other listener is given.
+    #[clap(long = "fd", value_name = "FD", conflicts_with_all = &["port", "interface"])]
+    pub(crate) fds: Vec<u32>,
+
+    /// Write where dummyhttp can be reached to this file once it accepts connections
+    ///
+    /// The file contains a JSON object with the URL and port of the first listener, the URLs of
+    /// all listeners and the pid, e.g. {"url":"http://127.0.0.1:41234","port":41234,"pid":4242,
+    /// "urls":["http://127.0.0.1:41234"]}. Use --port 0 to have the OS pick a free port.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub(crate) ready_file: Option<PathBuf>,
+
+    /// Print where dummyhttp can be reached instead of the startup banner
+    ///
+    /// The address is printed even with --quiet. json prints the same object as --ready-file.
+    #[clap(long, arg_enum, value_name = "FORMAT")]
+    pub(crate) print_address: Option<AddressFormat>,
+
+    /// How long open connections get to finish when shutting down on SIGINT or SIGTERM
+    ///
+    /// New connections aren't accepted anymore once shutting down. Connections still open after
+    /// this time are closed. Same duration format as --delay.
+    #[clap(
+        long,
+        value_name = "DURATION",
+        default_value = "10s",
+        parse(try_from_str = parse_duration)
+    )]
+    pub(crate) shutdown_timeout: Duration,
+
+    /// Write the summary of the served requests to this file on shutdown instead of printing it
+    ///
+    /// The summary is a JSON object with the number of requests in total and per route as well as
+    /// the unmatched requests and the requests dummyhttp failed to answer as configured.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub(crate) summary_file: Option<PathBuf>,
 
     /// Interface to bind to
     #[clap(
@@ -50,7 +289,7 @@ pub struct Args {
         number_of_values = 1,
         default_value = "0.0.0.0"
     )]
-    pub interface: IpAddr,
+    pub(crate) interface: IpAddr,
 
     /// Generate completion file for a shell
     #[clap(long = "print-completions", value_name = "shell", arg_enum)]
@@ -62,13 +301,100 @@ pub struct Args {
 
     /// TLS certificate to use
     #[cfg(feature = "tls")]
-    #[clap(long = "tls-cert", alias = "cert", requires = "tls-key", value_hint = ValueHint::FilePath)]
-    pub tls_cert: Option<PathBuf>,
+    #[clap(
+        long = "tls-cert",
+        alias = "cert",
+        requires = "tls-key",
+        group = "tls",
+        value_hint = ValueHint::FilePath
+    )]
+    pub(crate) tls_cert: Option<PathBuf>,
 
     /// TLS private key to use
     #[cfg(feature = "tls")]
     #[clap(long = "tls-key", alias = "key", requires = "tls-cert", value_hint = ValueHint::FilePath)]
-    pub tls_key: Option<PathBuf>,
+    pub(crate) tls_key: Option<PathBuf>,
+
+    /// Serve HTTPS using a certificate generated at startup
+    ///
+    /// The certificate is signed by an ephemeral CA whose SHA-256 fingerprint is printed on
+    /// startup. It is valid for localhost, 127.0.0.1, ::1, the interface and any --tls-san.
+    #[cfg(feature = "tls")]
+    #[clap(long, conflicts_with = "tls-cert", group = "tls")]
+    pub(crate) tls_self_signed: bool,
+
+    /// Additional hostname or IP address the --tls-self-signed certificate is valid for
+    #[cfg(feature = "tls")]
+    #[clap(long, value_name = "NAME", requires = "tls-self-signed")]
+    pub(crate) tls_san: Vec<String>,
+
+    /// Write the CA certificate of --tls-self-signed to this file so that clients can trust it
+    #[cfg(feature = "tls")]
+    #[clap(long, value_name = "PATH", requires = "tls-self-signed", value_hint = ValueHint::FilePath)]
+    pub(crate) tls_write_ca: Option<PathBuf>,
+
+    /// Verify client certificates against this CA (PEM), i.e. enable mutual TLS
+    ///
+    /// The subject, SANs and fingerprint of presented client certificates are logged and
+    /// available to body templates as `request.client_cert`.
+    #[cfg(feature = "tls")]
+    #[clap(long, value_name = "PATH", requires = "tls", value_hint = ValueHint::FilePath)]
+    pub(crate) tls_client_ca: Option<PathBuf>,
+
+    /// Whether clients have to present a certificate signed by --tls-client-ca
+    #[cfg(feature = "tls")]
+    #[clap(long, arg_enum, default_value = "required", requires = "tls-client-ca")]
+    pub(crate) tls_client_auth: ClientAuth,
+}
+
+impl Args {
+    /// The listeners given via --listen, --unix-socket and --fd, the sockets passed by systemd or
+    /// the one given by --interface and --port
+    ///
+    /// Inherited sockets and the latter speak HTTPS if a certificate is configured. File
+    /// descriptors given more than once are only used once, the first time they are given.
+    pub(crate) fn listeners(&self) -> Vec<Listen> {
+        let mut listeners = self.listen.clone();
+        if let Some(path) = &self.unix_socket {
+            listeners.push(Listen {
+                addr: ListenAddr::Unix(path.clone()),
+                tls: false,
+                config: None,
+            });
+        }
+        let mut fds = self.fds.clone();
+        if listeners.is_empty() && fds.is_empty() {
+            fds = listen::systemd_fds();
+        }
+        listeners.extend(fds.into_iter().map(|fd| Listen {
+            addr: ListenAddr::Fd(fd),
+            tls: self.tls(),
+            config: None,
+        }));
+        if listeners.is_empty() {
+            listeners.push(Listen {
+                addr: ListenAddr::Tcp(SocketAddr::from((self.interface, self.port))),
+                tls: self.tls(),
+                config: None,
+            });
+        }
+
+        // Adopting a descriptor twice would close it twice.
+        let mut fds = BTreeSet::new();
+        listeners.retain(|listen| match listen.addr {
+            ListenAddr::Fd(fd) => fds.insert(fd),
+            _ => true,
+        });
+        listeners
+    }
+
+    /// Whether a TLS certificate is configured
+    pub(crate) fn tls(&self) -> bool {
+        #[cfg(feature = "tls")]
+        return self.tls_cert.is_some() || self.tls_self_signed;
+        #[cfg(not(feature = "tls"))]
+        false
+    }
 }
 
 /// Checks wether an interface is valid, i.e. it can be parsed into an IP address
@@ -76,6 +402,15 @@ fn parse_interface(src: &str) -> Result<IpAddr, std::net::AddrParseError> {
     src.parse::<IpAddr>()
 }
 
+/// Checks whether an upstream is a valid http(s) URL
+fn parse_upstream(src: &str) -> Result<String, String> {
+    let uri = src.parse::<hyper::Uri>().map_err(|e| e.to_string())?;
+    match uri.scheme_str() {
+        Some("http") | Some("https") if uri.host().is_some() => Ok(src.to_string()),
+        _ => Err("Upstream must be an http:// or https:// URL".to_string()),
+    }
+}
+
 /// Parse a header given in a string format into a `HeaderMap`
 ///
 /// Headers are expected to be in format "key:value".
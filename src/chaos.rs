@@ -0,0 +1,400 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::{
+    body::{boxed, Body},
+    http::{HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use axum_server::accept::{Accept, DefaultAcceptor};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, StreamExt};
+use hyper::{header::CONTENT_LENGTH, server::conn::AddrStream};
+use rand::Rng;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::sync::watch;
+use tower_http::add_extension::AddExtension;
+
+use crate::metrics::{ConnectionGuard, Metrics};
+use crate::SentBody;
+
+/// A fault that is injected into a response with the given probability
+///
+/// Format: `<kind>[@<probability>]` where kind is one of
+///
+/// status=<code> - answer with a different status code
+/// reset - reset the TCP connection in the middle of the response
+/// close - close the connection without responding
+/// truncate - send only half of the body while announcing the full Content-Length
+/// malformed - send garbage instead of a valid HTTP response
+///
+/// The probability is given as a number between 0 and 1 and defaults to 1.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub probability: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    Status(StatusCode),
+    Reset,
+    Close,
+    Truncate,
+    Malformed,
+}
+
+/// Faults that may be injected into a response, attached by the handler for `inject_faults`
+#[derive(Debug, Clone)]
+pub struct Faults(pub Vec<Fault>);
+
+/// The fault that was actually injected into a response, kept around for logging
+#[derive(Debug, Clone, Copy)]
+pub struct InjectedFault(pub FaultKind);
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultKind::Status(status) => write!(f, "status={}", status.as_u16()),
+            FaultKind::Reset => write!(f, "reset"),
+            FaultKind::Close => write!(f, "close"),
+            FaultKind::Truncate => write!(f, "truncate"),
+            FaultKind::Malformed => write!(f, "malformed"),
+        }
+    }
+}
+
+impl FromStr for Fault {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Fault, String> {
+        let (kind, probability) = match s.split_once('@') {
+            Some((kind, probability)) => {
+                let probability = probability
+                    .parse::<f64>()
+                    .map_err(|_| format!("Invalid probability '{}'", probability))?;
+                if !(0.0..=1.0).contains(&probability) {
+                    return Err(format!(
+                        "Probability must be between 0 and 1 but is {}",
+                        probability
+                    ));
+                }
+                (kind, probability)
+            }
+            None => (s, 1.0),
+        };
+
+        let kind = match kind {
+            "reset" => FaultKind::Reset,
+            "close" => FaultKind::Close,
+            "truncate" => FaultKind::Truncate,
+            "malformed" => FaultKind::Malformed,
+            _ => match kind.strip_prefix("status=") {
+                Some(code) => FaultKind::Status(
+                    code.parse::<u16>()
+                        .ok()
+                        .and_then(|code| StatusCode::from_u16(code).ok())
+                        .ok_or_else(|| format!("Invalid status code '{}'", code))?,
+                ),
+                None => return Err(format!("Unknown fault '{}'", kind)),
+            },
+        };
+
+        Ok(Fault { kind, probability })
+    }
+}
+
+impl TryFrom<String> for Fault {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Fault, String> {
+        s.parse()
+    }
+}
+
+/// Make sure the probabilities of `faults` add up to at most 1 as only one is picked per response
+pub fn check_probabilities(faults: &[Fault]) -> anyhow::Result<()> {
+    let total = faults.iter().map(|fault| fault.probability).sum::<f64>();
+    // Allow for rounding, e.g. 0.33 + 0.56 + 0.11 is slightly more than 1.
+    if total > 1.0 + 1e-9 {
+        anyhow::bail!(
+            "Fault probabilities add up to {} but must not exceed 1",
+            total
+        );
+    }
+    Ok(())
+}
+
+/// Roll the dice for a list of faults
+///
+/// A single random number is drawn and the faults are checked in order against their
+/// cumulative probability so that at most one fault is picked per response.
+pub fn pick(faults: &[Fault]) -> Option<FaultKind> {
+    let roll = rand::thread_rng().gen::<f64>();
+    let mut cumulative = 0.0;
+    for fault in faults {
+        cumulative += fault.probability;
+        if roll < cumulative {
+            return Some(fault.kind);
+        }
+    }
+    None
+}
+
+/// Middleware injecting the faults attached to a response by the handler
+///
+/// This runs inside of the logging middleware so that injected faults show up in the log.
+pub async fn inject_faults(req: Request<Body>, next: Next<Body>) -> Response {
+    let control = req.extensions().get::<ConnectionControl>().cloned();
+    let mut resp = next.run(req).await;
+
+    let fault = match resp.extensions().get::<Faults>() {
+        Some(faults) => pick(&faults.0),
+        None => None,
+    };
+    let fault = match fault {
+        Some(fault) => fault,
+        None => return resp,
+    };
+
+    match fault {
+        FaultKind::Status(status) => *resp.status_mut() = status,
+        FaultKind::Truncate => {
+            let body = resp
+                .extensions()
+                .get::<SentBody>()
+                .map(|body| body.0.clone())
+                .unwrap_or_default();
+
+            // The body is sent as a stream as hyper would otherwise correct the Content-Length
+            // for us. The stream fails after the first half, which makes hyper close the
+            // connection without sending the rest. hyper drops what it hasn't written yet when
+            // that happens, so the stream only fails once the first half was flushed.
+            let truncated = body.slice(..body.len() / 2);
+            let fail = async move {
+                if let Some(control) = control {
+                    control.flushed().await;
+                }
+                Err(io::Error::other("truncated body"))
+            };
+            let stream = stream::once(async { Ok(truncated) }).chain(stream::once(fail));
+            resp.headers_mut()
+                .insert(CONTENT_LENGTH, HeaderValue::from(body.len().max(1)));
+            *resp.body_mut() = boxed(Body::wrap_stream(stream));
+        }
+        FaultKind::Reset | FaultKind::Close | FaultKind::Malformed => match control {
+            Some(control) => control.set(fault),
+            None => return resp,
+        },
+    }
+
+    resp.extensions_mut().insert(InjectedFault(fault));
+    resp
+}
+
+/// Per-connection handle used by `inject_faults` to tell `ChaosStream` to misbehave
+#[derive(Debug, Clone)]
+pub struct ConnectionControl {
+    fault: Arc<Mutex<Option<FaultKind>>>,
+
+    /// Notified whenever the connection was flushed
+    flushes: Arc<watch::Sender<()>>,
+}
+
+impl Default for ConnectionControl {
+    fn default() -> ConnectionControl {
+        ConnectionControl {
+            fault: Default::default(),
+            flushes: Arc::new(watch::channel(()).0),
+        }
+    }
+}
+
+impl ConnectionControl {
+    fn set(&self, fault: FaultKind) {
+        *self.fault.lock().unwrap() = Some(fault);
+    }
+
+    fn get(&self) -> Option<FaultKind> {
+        *self.fault.lock().unwrap()
+    }
+
+    /// Wait until everything written to the connection so far is flushed
+    async fn flushed(&self) {
+        let _ = self.flushes.subscribe().changed().await;
+    }
+}
+
+/// Acceptor wrapping every connection in a `ChaosStream`
+///
+/// The `ConnectionControl` of the connection is added to every request on it so that
+/// `inject_faults` can get hold of it. As every connection passes through here, this is also
+/// where active connections are counted for the metrics.
+#[derive(Debug, Clone)]
+pub struct ChaosAcceptor<A = DefaultAcceptor> {
+    inner: A,
+    metrics: Metrics,
+}
+
+impl ChaosAcceptor {
+    pub fn new(metrics: Metrics) -> ChaosAcceptor {
+        ChaosAcceptor {
+            inner: DefaultAcceptor::new(),
+            metrics,
+        }
+    }
+}
+
+impl<A> ChaosAcceptor<A> {
+    /// Hand the wrapped connections on to `inner`
+    pub fn acceptor<B>(self, inner: B) -> ChaosAcceptor<B> {
+        ChaosAcceptor {
+            inner,
+            metrics: self.metrics,
+        }
+    }
+}
+
+impl<A> ChaosAcceptor<A> {
+    fn wrap<T>(&self, stream: T) -> (ChaosStream<T>, ConnectionControl) {
+        let control = ConnectionControl::default();
+        let stream = ChaosStream {
+            inner: stream,
+            control: control.clone(),
+            reset_pending: false,
+            _connection: self.metrics.connection(),
+        };
+        (stream, control)
+    }
+}
+
+impl<A, S> Accept<AddrStream, S> for ChaosAcceptor<A>
+where
+    A: Accept<ChaosStream, AddExtension<S, ConnectionControl>> + Clone + Send + 'static,
+    A::Future: Send,
+    S: Send + 'static,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: AddrStream, service: S) -> Self::Future {
+        let (stream, control) = self.wrap(stream.into_inner());
+        let service = AddExtension::new(service, control);
+        let inner = self.inner.clone();
+        Box::pin(async move { inner.accept(stream, service).await })
+    }
+}
+
+#[cfg(unix)]
+impl<A, S> Accept<UnixStream, S> for ChaosAcceptor<A>
+where
+    A: Accept<ChaosStream<UnixStream>, AddExtension<S, ConnectionControl>> + Clone + Send + 'static,
+    A::Future: Send,
+    S: Send + 'static,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: UnixStream, service: S) -> Self::Future {
+        let (stream, control) = self.wrap(stream);
+        let service = AddExtension::new(service, control);
+        let inner = self.inner.clone();
+        Box::pin(async move { inner.accept(stream, service).await })
+    }
+}
+
+/// Streams that can be made to end abruptly once dropped
+pub trait Reset {
+    fn reset_on_drop(&self) -> io::Result<()>;
+}
+
+impl Reset for TcpStream {
+    /// Dropping a socket with a zero linger timeout sends a RST instead of a FIN.
+    fn reset_on_drop(&self) -> io::Result<()> {
+        self.set_linger(Some(Duration::ZERO))
+    }
+}
+
+/// Unix sockets have no notion of a reset, the peer sees the connection closed in the middle of
+/// the response.
+#[cfg(unix)]
+impl Reset for UnixStream {
+    fn reset_on_drop(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A stream that can be told to reset, close or send garbage instead of the response
+#[derive(Debug)]
+pub struct ChaosStream<T = TcpStream> {
+    inner: T,
+    control: ConnectionControl,
+    reset_pending: bool,
+    _connection: ConnectionGuard,
+}
+
+const MALFORMED_RESPONSE: &[u8] = b"HTTP/1.1 ??? Malformed\r\nthis is not a header\r\n\r\n";
+
+impl<T: AsyncRead + Unpin> AsyncRead for ChaosStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Reset + Unpin> AsyncWrite for ChaosStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.control.get() {
+            None => Pin::new(&mut self.inner).poll_write(cx, buf),
+            Some(FaultKind::Close) => Poll::Ready(Err(io::ErrorKind::ConnectionAborted.into())),
+            Some(FaultKind::Reset) => {
+                if self.reset_pending || buf.len() < 2 {
+                    self.inner.reset_on_drop()?;
+                    return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+                }
+                // Let half of the response through before resetting.
+                let half = buf.len() / 2;
+                let written =
+                    futures_util::ready!(Pin::new(&mut self.inner).poll_write(cx, &buf[..half]))?;
+                self.reset_pending = true;
+                Poll::Ready(Ok(written))
+            }
+            Some(FaultKind::Malformed) => {
+                futures_util::ready!(Pin::new(&mut self.inner).poll_write(cx, MALFORMED_RESPONSE))?;
+                Poll::Ready(Err(io::ErrorKind::ConnectionAborted.into()))
+            }
+            Some(_) => Pin::new(&mut self.inner).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_util::ready!(Pin::new(&mut self.inner).poll_flush(cx))?;
+        self.control.flushes.send_replace(());
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
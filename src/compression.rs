@@ -0,0 +1,271 @@
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use axum::body::Bytes;
+use axum::http::{HeaderMap, HeaderValue};
+use flate2::{read, write, Compression as Level};
+use hyper::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+use serde::Deserialize;
+
+/// A content coding we can compress response bodies with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+    Br,
+    Zstd,
+}
+
+/// Decoded bodies are cut off after this many bytes so that small bombs can't exhaust memory
+pub const DECODE_LIMIT: u64 = 1024 * 1024;
+
+/// Appended to logged bodies which were cut off after `DECODE_LIMIT` bytes
+pub const TRUNCATED_MARKER: &str = "... (truncated)";
+
+/// Encodings in the order we prefer them when the client likes several equally
+const PREFERRED: &[Encoding] = &[
+    Encoding::Zstd,
+    Encoding::Br,
+    Encoding::Gzip,
+    Encoding::Deflate,
+];
+
+/// How response bodies are compressed
+///
+/// Supported formats:
+///
+/// auto - pick an encoding based on the request's Accept-Encoding header
+/// off - never compress
+/// gzip, deflate, br or zstd - always use this encoding, no matter what the client accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Compression {
+    Auto,
+    Off,
+    Force(Encoding),
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Br => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// Compress `body`
+    ///
+    /// `deflate` is the zlib format as specified for HTTP rather than a raw deflate stream.
+    pub fn encode(self, body: &[u8]) -> io::Result<Bytes> {
+        let encoded = match self {
+            Encoding::Gzip => {
+                let mut encoder = write::GzEncoder::new(vec![], Level::default());
+                encoder.write_all(body)?;
+                encoder.finish()?
+            }
+            Encoding::Deflate => {
+                let mut encoder = write::ZlibEncoder::new(vec![], Level::default());
+                encoder.write_all(body)?;
+                encoder.finish()?
+            }
+            Encoding::Br => {
+                let mut encoded = vec![];
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut encoded, 4096, 5, 22);
+                    encoder.write_all(body)?;
+                }
+                encoded
+            }
+            Encoding::Zstd => zstd::encode_all(body, 0)?,
+        };
+        Ok(Bytes::from(encoded))
+    }
+
+    /// Decompress `body`, reading at most one byte more than `limit` to detect truncation
+    fn decode(self, body: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+        let mut decoded = vec![];
+        let limit = limit + 1;
+        match self {
+            Encoding::Gzip => read::MultiGzDecoder::new(body)
+                .take(limit)
+                .read_to_end(&mut decoded)?,
+            Encoding::Deflate => read::ZlibDecoder::new(body)
+                .take(limit)
+                .read_to_end(&mut decoded)?,
+            Encoding::Br => brotli::Decompressor::new(body, 4096)
+                .take(limit)
+                .read_to_end(&mut decoded)?,
+            Encoding::Zstd => zstd::Decoder::new(body)?
+                .take(limit)
+                .read_to_end(&mut decoded)?,
+        };
+        Ok(decoded)
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Encoding, String> {
+        match s.trim().to_lowercase().as_str() {
+            "gzip" | "x-gzip" => Ok(Encoding::Gzip),
+            "deflate" => Ok(Encoding::Deflate),
+            "br" => Ok(Encoding::Br),
+            "zstd" => Ok(Encoding::Zstd),
+            _ => Err(format!("Unknown encoding '{}'", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Encoding {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Encoding, String> {
+        s.parse()
+    }
+}
+
+impl Compression {
+    /// The encoding to use for a response to a request with the given headers, if any
+    pub fn pick(self, req_headers: &HeaderMap) -> Option<Encoding> {
+        match self {
+            Compression::Auto => negotiate(req_headers),
+            Compression::Off => None,
+            Compression::Force(encoding) => Some(encoding),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Compression, String> {
+        match s.trim() {
+            "auto" => Ok(Compression::Auto),
+            "off" => Ok(Compression::Off),
+            other => other
+                .parse()
+                .map(Compression::Force)
+                .map_err(|_| format!("Unknown compression '{}'", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Compression {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Compression, String> {
+        s.parse()
+    }
+}
+
+/// Pick the encoding the client gave the highest quality in its Accept-Encoding header
+///
+/// Ties are broken by `PREFERRED`. Encodings with a quality of 0 are never picked and `*`
+/// stands for every encoding not listed explicitly.
+fn negotiate(req_headers: &HeaderMap) -> Option<Encoding> {
+    let accepted = req_headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|coding| {
+            let mut params = coding.split(';');
+            let name = params.next()?.trim().to_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((name, quality))
+        })
+        .collect::<Vec<_>>();
+
+    let quality = |encoding: Encoding| {
+        accepted
+            .iter()
+            .find(|(name, _)| name.parse() == Ok(encoding))
+            .or_else(|| accepted.iter().find(|(name, _)| name == "*"))
+            .map(|(_, quality)| *quality)
+            .unwrap_or(0.0)
+    };
+    PREFERRED
+        .iter()
+        .copied()
+        .map(|encoding| (encoding, quality(encoding)))
+        .filter(|(_, quality)| *quality > 0.0)
+        .fold(
+            None,
+            |best: Option<(Encoding, f32)>, candidate| match best {
+                Some(best) if best.1 >= candidate.1 => Some(best),
+                _ => Some(candidate),
+            },
+        )
+        .map(|(encoding, _)| encoding)
+}
+
+/// A body with its Content-Encoding undone, see `decode`
+#[derive(Debug)]
+pub struct Decoded {
+    pub body: Bytes,
+
+    /// Whether the body was cut off after `DECODE_LIMIT` bytes
+    pub truncated: bool,
+}
+
+/// Undo the Content-Encoding of a body so that it can be logged
+///
+/// Bodies with unknown encodings or which fail to decode are returned as they are. Decoded
+/// bodies are cut off after `DECODE_LIMIT` bytes.
+pub fn decode(headers: &HeaderMap, body: &Bytes) -> Decoded {
+    let encodings = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty() && !name.eq_ignore_ascii_case("identity"))
+                .map(|name| name.parse::<Encoding>())
+                .collect::<Result<Vec<_>, _>>()
+        });
+    let encodings = match encodings {
+        Some(Ok(encodings)) if !encodings.is_empty() => encodings,
+        _ => {
+            return Decoded {
+                body: body.clone(),
+                truncated: false,
+            }
+        }
+    };
+
+    // Encodings are listed in the order they were applied.
+    let decoded =
+        encodings
+            .iter()
+            .rev()
+            .try_fold((body.to_vec(), false), |(body, truncated), encoding| {
+                let mut decoded = encoding.decode(&body, DECODE_LIMIT)?;
+                let cut = decoded.len() as u64 > DECODE_LIMIT;
+                decoded.truncate(DECODE_LIMIT as usize);
+                io::Result::Ok((decoded, truncated || cut))
+            });
+    match decoded {
+        Ok((decoded, truncated)) => Decoded {
+            body: Bytes::from(decoded),
+            truncated,
+        },
+        Err(_) => Decoded {
+            body: body.clone(),
+            truncated: false,
+        },
+    }
+}
+
+/// Value for a Content-Encoding header
+pub fn header_value(encoding: Encoding) -> HeaderValue {
+    HeaderValue::from_static(encoding.name())
+}
@@ -0,0 +1,514 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::chaos::Fault;
+use crate::compression::Compression;
+use crate::delay::Delay;
+use crate::streaming::Bandwidth;
+
+/// Route configuration as read from a `--config` file
+///
+/// The file can be written in YAML, TOML or JSON. The format is picked based on the file
+/// extension.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Routes in the order in which they are matched
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+
+    /// Response for requests not matching any route, defaults to the one given on the command
+    /// line
+    #[serde(default)]
+    pub default: Option<ResponseConfig>,
+}
+
+/// A single route, i.e. which requests to match and what to answer with
+#[derive(Debug, Clone, Deserialize)]
+pub struct RouteConfig {
+    /// HTTP method(s) to match, all methods are matched if not given
+    #[serde(default)]
+    pub(crate) method: Option<OneOrMany<String>>,
+
+    /// Path to match, either a pattern (e.g. `/users/:id` or `/static/*rest`) or a matcher
+    pub(crate) path: PathConfig,
+
+    /// Further conditions on the request
+    #[serde(default)]
+    pub(crate) request: RequestConfig,
+
+    /// Scenario this route belongs to
+    #[serde(default)]
+    pub(crate) scenario: Option<String>,
+
+    /// Only match while the scenario is in this state (scenarios start in `started`)
+    #[serde(default)]
+    pub(crate) required_state: Option<String>,
+
+    /// Move the scenario into this state once the route matched
+    #[serde(default)]
+    pub(crate) new_state: Option<String>,
+
+    /// The response to send
+    #[serde(flatten)]
+    pub(crate) response: ResponseConfig,
+
+    /// Fields that are neither part of the route nor of the response, rejected on load
+    ///
+    /// `deny_unknown_fields` doesn't work together with `flatten`, so they are collected here.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, Value>,
+}
+
+/// A response as given in a route
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseConfig {
+    /// HTTP status code to send
+    #[serde(default = "default_code")]
+    pub code: u16,
+
+    /// Headers to send
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// HTTP body to send, rendered as a Tera template
+    #[serde(default)]
+    pub body: String,
+
+    /// File to send as body, relative to the config file
+    #[serde(default)]
+    pub body_file: Option<PathBuf>,
+
+    /// Render `body_file` as a Tera template instead of sending it as is
+    #[serde(default)]
+    pub template: bool,
+
+    /// Delay before sending the response (same format as --delay)
+    #[serde(default)]
+    pub delay: Option<Delay>,
+
+    /// Spread the body over this duration (same format as --trickle)
+    #[serde(default)]
+    pub trickle: Option<Delay>,
+
+    /// Send the body with chunked transfer encoding in chunks of this many bytes
+    #[serde(default)]
+    pub chunk_size: Option<usize>,
+
+    /// Wait this long between chunks (same format as --chunk-delay)
+    #[serde(default)]
+    pub chunk_delay: Option<Delay>,
+
+    /// Send the body no faster than this many bytes per second (same format as --bandwidth)
+    #[serde(default)]
+    pub bandwidth: Option<Bandwidth>,
+
+    /// How to compress the body (same format as --compression), defaults to the compression
+    /// given on the command line
+    #[serde(default)]
+    pub compression: Option<Compression>,
+
+    /// Content-Encoding header to send no matter how the body is actually encoded
+    #[serde(default)]
+    pub fake_content_encoding: Option<String>,
+
+    /// Faults to inject (same format as --fault), defaults to the faults given on the command
+    /// line
+    #[serde(default)]
+    pub faults: Option<Vec<Fault>>,
+
+    /// Upgrade matching requests to a WebSocket following this script instead of answering them
+    #[serde(default)]
+    pub websocket: Option<WebSocketConfig>,
+
+    /// Stream these Server-Sent Events as body, which rules out all other body settings
+    #[serde(default)]
+    pub sse: Option<SseConfig>,
+}
+
+/// A stream of Server-Sent Events
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SseConfig {
+    /// Events to send in order
+    pub events: Vec<EventConfig>,
+
+    /// Start over once all events were sent, forever
+    #[serde(default)]
+    pub repeat: bool,
+
+    /// Continue after the event whose id was sent as `Last-Event-ID` by reconnecting clients
+    #[serde(default)]
+    pub resume: bool,
+}
+
+/// A single Server-Sent Event
+///
+/// `id`, `event` and `data` are rendered as Tera templates with the request available as
+/// `request`, the number of the event in the stream as `index` (starting at 0) and the number of
+/// times the events were repeated as `iteration`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventConfig {
+    #[serde(default)]
+    pub id: Option<String>,
+
+    /// Event type
+    #[serde(default)]
+    pub event: Option<String>,
+
+    /// Event data, sent as multiple `data` lines if it contains line breaks
+    #[serde(default)]
+    pub data: String,
+
+    /// Reconnection time in milliseconds to tell the client
+    #[serde(default)]
+    pub retry: Option<u64>,
+
+    /// Wait this long before sending the event (same format as --delay)
+    #[serde(default)]
+    pub delay: Option<Delay>,
+}
+
+/// What to do on a WebSocket connection
+///
+/// The messages in `send` are sent right after the upgrade. Afterwards the connection is closed
+/// if `close` is given. Otherwise incoming messages are answered by the first matching entry of
+/// `on_message` or echoed back if `echo` is set.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// Messages to send after the upgrade
+    #[serde(default)]
+    pub send: Vec<MessageConfig>,
+
+    /// How to react to incoming messages, the first matching entry is used
+    #[serde(default)]
+    pub on_message: Vec<ReplyConfig>,
+
+    /// Send back incoming messages that didn't match any entry of `on_message`
+    #[serde(default)]
+    pub echo: bool,
+
+    /// Close the connection once the messages in `send` were sent
+    #[serde(default)]
+    pub close: Option<CloseConfig>,
+}
+
+/// A WebSocket message to send
+///
+/// Exactly one of `text` and `binary` has to be given. Text messages are rendered as Tera
+/// templates with the upgrade request available as `request` and, for replies, the incoming
+/// message as `message`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageConfig {
+    #[serde(default)]
+    pub text: Option<String>,
+
+    /// Base64 encoded binary message
+    #[serde(default)]
+    pub binary: Option<String>,
+
+    /// Wait this long before sending the message (same format as --delay)
+    #[serde(default)]
+    pub delay: Option<Delay>,
+}
+
+/// Reaction to incoming WebSocket messages
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplyConfig {
+    /// Conditions the message needs to fulfill (same as for request bodies), all messages match
+    /// if not given
+    #[serde(default, rename = "match")]
+    pub matches: Option<OneOrMany<BodyMatchConfig>>,
+
+    /// Messages to answer with
+    #[serde(default)]
+    pub reply: Option<OneOrMany<MessageConfig>>,
+
+    /// Close the connection after replying
+    #[serde(default)]
+    pub close: Option<CloseConfig>,
+}
+
+/// How to close a WebSocket connection
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CloseConfig {
+    /// Close code to send
+    #[serde(default = "default_close_code")]
+    pub code: u16,
+
+    #[serde(default)]
+    pub reason: String,
+
+    /// Wait this long before closing (same format as --delay)
+    #[serde(default)]
+    pub delay: Option<Delay>,
+}
+
+/// How to match the request path
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum PathConfig {
+    /// A pattern with `:name` and `*name` placeholders
+    Pattern(String),
+
+    Matcher(PathMatchConfig),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathMatchConfig {
+    /// The path has to be exactly this
+    Exact(String),
+
+    /// The path has to start with this
+    Prefix(String),
+
+    /// The path has to match this regex, named groups are captured like `:name` placeholders
+    Regex(String),
+
+    /// The path has to match this glob (`*` and `?` don't match `/`, `**` matches anything)
+    Glob(String),
+}
+
+/// Conditions on the query, headers and body of a request
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequestConfig {
+    /// Query parameters the request needs to have
+    #[serde(default)]
+    pub query: BTreeMap<String, ValueConfig>,
+
+    /// Headers the request needs to have
+    #[serde(default)]
+    pub headers: BTreeMap<String, ValueConfig>,
+
+    /// Conditions the body needs to fulfill
+    #[serde(default)]
+    pub body: Option<OneOrMany<BodyMatchConfig>>,
+}
+
+/// Condition on a query parameter or header, either a value it has to equal or a matcher
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ValueConfig {
+    Matcher(ValueMatchConfig),
+    Equals(Value),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueMatchConfig {
+    Equals(String),
+    Contains(String),
+    Regex(String),
+    Present(bool),
+}
+
+/// Condition on the request body
+///
+/// Exactly one of `contains`, `regex`, `json_path` and `json` has to be given. `json_path`
+/// checks that the path exists or, if `equals` is given as well, that it has that value. `json`
+/// checks that the body contains the given JSON, ignoring additional fields.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BodyMatchConfig {
+    #[serde(default)]
+    pub contains: Option<String>,
+
+    #[serde(default)]
+    pub regex: Option<String>,
+
+    #[serde(default)]
+    pub json_path: Option<String>,
+
+    #[serde(default)]
+    pub equals: Option<Value>,
+
+    #[serde(default)]
+    pub json: Option<Value>,
+}
+
+impl fmt::Display for PathConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathConfig::Pattern(pattern) => write!(f, "{}", pattern),
+            PathConfig::Matcher(PathMatchConfig::Exact(path)) => write!(f, "exact {}", path),
+            PathConfig::Matcher(PathMatchConfig::Prefix(path)) => write!(f, "prefix {}", path),
+            PathConfig::Matcher(PathMatchConfig::Regex(regex)) => write!(f, "regex {}", regex),
+            PathConfig::Matcher(PathMatchConfig::Glob(glob)) => write!(f, "glob {}", glob),
+        }
+    }
+}
+
+/// Either a single value or a list of values
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    pub fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+fn default_code() -> u16 {
+    200
+}
+
+fn default_close_code() -> u16 {
+    1000
+}
+
+impl Default for ResponseConfig {
+    fn default() -> ResponseConfig {
+        ResponseConfig {
+            code: default_code(),
+            headers: BTreeMap::new(),
+            body: String::new(),
+            body_file: None,
+            template: false,
+            delay: None,
+            trickle: None,
+            chunk_size: None,
+            chunk_delay: None,
+            bandwidth: None,
+            compression: None,
+            fake_content_encoding: None,
+            faults: None,
+            websocket: None,
+            sse: None,
+        }
+    }
+}
+
+impl RouteConfig {
+    /// A route matching all methods on `path` and answering with an empty 200
+    pub fn new(path: impl Into<String>) -> RouteConfig {
+        RouteConfig {
+            method: None,
+            path: PathConfig::Pattern(path.into()),
+            request: RequestConfig::default(),
+            scenario: None,
+            required_state: None,
+            new_state: None,
+            response: ResponseConfig::default(),
+            unknown: BTreeMap::new(),
+        }
+    }
+
+    /// Only match this method, may be given multiple times
+    pub fn method(mut self, method: impl Into<String>) -> RouteConfig {
+        let mut methods = self
+            .method
+            .take()
+            .map(OneOrMany::into_vec)
+            .unwrap_or_default();
+        methods.push(method.into());
+        self.method = Some(OneOrMany::Many(methods));
+        self
+    }
+
+    /// Only match requests with this query parameter
+    pub fn query(mut self, key: impl Into<String>, value: impl Into<String>) -> RouteConfig {
+        self.request
+            .query
+            .insert(key.into(), ValueConfig::Equals(Value::String(value.into())));
+        self
+    }
+
+    /// Only match requests with this header
+    pub fn request_header(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> RouteConfig {
+        self.request
+            .headers
+            .insert(key.into(), ValueConfig::Equals(Value::String(value.into())));
+        self
+    }
+
+    pub fn code(mut self, code: u16) -> RouteConfig {
+        self.response.code = code;
+        self
+    }
+
+    pub fn header(mut self, key: impl Into<String>, value: impl Into<String>) -> RouteConfig {
+        self.response.headers.insert(key.into(), value.into());
+        self
+    }
+
+    /// Body to send, rendered as a Tera template
+    pub fn body(mut self, body: impl Into<String>) -> RouteConfig {
+        self.response.body = body.into();
+        self
+    }
+
+    pub fn scenario(mut self, scenario: impl Into<String>) -> RouteConfig {
+        self.scenario = Some(scenario.into());
+        self
+    }
+
+    pub fn required_state(mut self, state: impl Into<String>) -> RouteConfig {
+        self.required_state = Some(state.into());
+        self
+    }
+
+    pub fn new_state(mut self, state: impl Into<String>) -> RouteConfig {
+        self.new_state = Some(state.into());
+        self
+    }
+}
+
+impl Config {
+    /// Load a config file, guessing its format from the file extension
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file '{}'", path.display()))?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let config = match extension.as_str() {
+            "yaml" | "yml" => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+            "toml" => toml::from_str(&content).map_err(anyhow::Error::from),
+            "json" => serde_json::from_str(&content).map_err(anyhow::Error::from),
+            _ => bail!(
+                "Unknown config file format for '{}' (expected .yaml, .yml, .toml or .json)",
+                path.display()
+            ),
+        };
+        config
+            .and_then(Config::check_unknown_fields)
+            .with_context(|| format!("Failed to parse config file '{}'", path.display()))
+    }
+
+    /// Reject routes with fields we don't know, most likely typos
+    fn check_unknown_fields(self) -> Result<Config> {
+        for (index, route) in self.routes.iter().enumerate() {
+            if let Some(field) = route.unknown.keys().next() {
+                bail!("unknown field `{}` in route #{}", field, index + 1);
+            }
+        }
+        Ok(self)
+    }
+}
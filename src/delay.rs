@@ -0,0 +1,124 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal, Normal};
+use serde::Deserialize;
+
+/// A possibly random duration used to simulate latency
+///
+/// Supported formats:
+///
+/// 500ms - always wait 500ms
+/// 100ms..300ms - wait a uniformly distributed time between 100ms and 300ms
+/// normal:200ms,50ms - normal distribution with a mean of 200ms and a standard deviation of 50ms
+/// lognormal:200ms,50ms - log-normal distribution with a mean of 200ms and a standard
+///   deviation of 50ms
+///
+/// Durations are given as a number followed by one of the units `us`, `ms`, `s` or `m`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Delay {
+    Fixed(Duration),
+    Uniform(Duration, Duration),
+    Normal { mean: Duration, std_dev: Duration },
+    LogNormal { mean: Duration, std_dev: Duration },
+}
+
+impl Delay {
+    /// Pick a duration from this delay's distribution
+    ///
+    /// Samples are clamped to zero as the normal distribution may produce negative values.
+    pub fn sample(&self) -> Duration {
+        let secs = match self {
+            Delay::Fixed(duration) => return *duration,
+            Delay::Uniform(from, to) => {
+                return rand::thread_rng().gen_range(*from..=*to);
+            }
+            Delay::Normal { mean, std_dev } => {
+                Normal::new(mean.as_secs_f64(), std_dev.as_secs_f64())
+                    .map(|normal| normal.sample(&mut rand::thread_rng()))
+                    .unwrap_or_else(|_| mean.as_secs_f64())
+            }
+            Delay::LogNormal { mean, std_dev } => {
+                // Convert the mean and standard deviation of the resulting distribution into
+                // the parameters of the underlying normal distribution.
+                let mean = mean.as_secs_f64();
+                let variance = std_dev.as_secs_f64().powi(2);
+                let sigma_squared = (1.0 + variance / mean.powi(2)).ln();
+                let mu = mean.ln() - sigma_squared / 2.0;
+                LogNormal::new(mu, sigma_squared.sqrt())
+                    .map(|lognormal| lognormal.sample(&mut rand::thread_rng()))
+                    .unwrap_or(mean)
+            }
+        };
+        Duration::try_from_secs_f64(secs).unwrap_or_default()
+    }
+}
+
+impl FromStr for Delay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Delay, String> {
+        let s = s.trim();
+        if let Some(params) = s.strip_prefix("normal:") {
+            let (mean, std_dev) = parse_mean_std_dev(params)?;
+            Ok(Delay::Normal { mean, std_dev })
+        } else if let Some(params) = s.strip_prefix("lognormal:") {
+            let (mean, std_dev) = parse_mean_std_dev(params)?;
+            if mean.is_zero() {
+                return Err("Mean of lognormal delay must be greater than zero".to_string());
+            }
+            Ok(Delay::LogNormal { mean, std_dev })
+        } else if let Some((from, to)) = s.split_once("..") {
+            let (from, to) = (parse_duration(from)?, parse_duration(to)?);
+            if from > to {
+                return Err(format!("Invalid delay range '{}' (start is after end)", s));
+            }
+            Ok(Delay::Uniform(from, to))
+        } else {
+            Ok(Delay::Fixed(parse_duration(s)?))
+        }
+    }
+}
+
+impl TryFrom<String> for Delay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Delay, String> {
+        s.parse()
+    }
+}
+
+fn parse_mean_std_dev(params: &str) -> Result<(Duration, Duration), String> {
+    let (mean, std_dev) = params
+        .split_once(',')
+        .ok_or_else(|| format!("Expected '<mean>,<std dev>' but got '{}'", params))?;
+    Ok((parse_duration(mean)?, parse_duration(std_dev)?))
+}
+
+/// Parse a duration such as `250ms` or `1.5s`
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .ok_or_else(|| format!("Missing unit in duration '{}' (use us, ms, s or m)", s))?;
+    let (value, unit) = s.split_at(split);
+    let value = value
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid duration '{}'", s))?;
+    let secs = match unit {
+        "us" => value / 1_000_000.0,
+        "ms" => value / 1_000.0,
+        "s" => value,
+        "m" => value * 60.0,
+        _ => {
+            return Err(format!(
+                "Unknown unit in duration '{}' (use us, ms, s or m)",
+                s
+            ))
+        }
+    };
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("Duration '{}' is too long", s))
+}
@@ -0,0 +1,36 @@
+use std::path::{Component, Path, PathBuf};
+
+use axum::body::Bytes;
+use axum::http::HeaderValue;
+use percent_encoding::percent_decode_str;
+
+/// Guess the Content-Type of a file from its extension
+pub fn guess_content_type(path: &Path) -> Option<HeaderValue> {
+    mime_guess::from_path(path)
+        .first()
+        .and_then(|mime| HeaderValue::from_str(mime.as_ref()).ok())
+}
+
+/// Look up the file for a request path in the `--serve-dir` directory
+///
+/// Directories are answered with their `index.html`. Paths trying to escape the directory are
+/// never matched.
+pub async fn find_file(dir: &Path, request_path: &str) -> Option<(PathBuf, Bytes)> {
+    let decoded = percent_decode_str(request_path).decode_utf8().ok()?;
+
+    let mut path = dir.to_path_buf();
+    for component in Path::new(decoded.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    let metadata = tokio::fs::metadata(&path).await.ok()?;
+    if metadata.is_dir() {
+        path.push("index.html");
+    }
+    let content = tokio::fs::read(&path).await.ok()?;
+    Some((path, Bytes::from(content)))
+}
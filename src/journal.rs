@@ -0,0 +1,117 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+/// A request as recorded in the `Journal`
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalEntry {
+    pub timestamp: String,
+    pub peer: String,
+    pub method: String,
+    pub uri: String,
+    pub path: String,
+    pub query: Option<String>,
+
+    /// Headers with lowercase names, repeated headers are joined with ", "
+    pub headers: BTreeMap<String, String>,
+
+    pub body: String,
+    pub status: u16,
+}
+
+/// In-memory record of the most recent requests
+///
+/// Only the last `capacity` requests are kept so that long running instances don't grow
+/// indefinitely.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    entries: Arc<Mutex<VecDeque<JournalEntry>>>,
+    capacity: usize,
+}
+
+/// Criteria to select journal entries by, given as query parameters to the admin API
+///
+/// `header` is given as `key:value` (or just `key` to check for presence) and may be repeated.
+#[derive(Debug, Default)]
+pub struct JournalFilter {
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub header: Vec<String>,
+}
+
+impl Journal {
+    pub fn new(capacity: usize) -> Journal {
+        Journal {
+            entries: Arc::new(Mutex::new(VecDeque::new())),
+            capacity,
+        }
+    }
+
+    pub fn record(&self, entry: JournalEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// All entries matching the filter, oldest first
+    pub fn list(&self, filter: &JournalFilter) -> Vec<JournalEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl JournalFilter {
+    /// Parse a filter from a query string
+    pub fn from_query(query: &str) -> Result<JournalFilter, String> {
+        let pairs = serde_urlencoded::from_str::<Vec<(String, String)>>(query)
+            .map_err(|e| e.to_string())?;
+        let mut filter = JournalFilter::default();
+        for (key, value) in pairs {
+            match key.as_str() {
+                "method" => filter.method = Some(value),
+                "path" => filter.path = Some(value),
+                "header" => filter.header.push(value),
+                _ => return Err(format!("Unknown filter '{}'", key)),
+            }
+        }
+        Ok(filter)
+    }
+
+    fn matches(&self, entry: &JournalEntry) -> bool {
+        if let Some(method) = &self.method {
+            if !method.eq_ignore_ascii_case(&entry.method) {
+                return false;
+            }
+        }
+        if let Some(path) = &self.path {
+            if path != &entry.path {
+                return false;
+            }
+        }
+        self.header.iter().all(|header| {
+            // Allow `key: value` as in HTTP.
+            let (key, value) = header.split_once(':').unwrap_or((header, ""));
+            let (key, value) = (key.trim(), value.trim());
+            entry
+                .headers
+                .get(&key.to_lowercase())
+                .map(|entry_value| value.is_empty() || entry_value == value)
+                .unwrap_or(false)
+        })
+    }
+}
@@ -0,0 +1,1064 @@
+//! dummyhttp is a super simple HTTP server that replies with a fixed body and a fixed response
+//! code, or whatever routes you configure for it.
+//!
+//! Besides the `dummyhttp` binary, this crate can be used as a library to run the same server
+//! in-process for tests:
+//!
+//! ```
+//! use dummyhttp::{DummyServer, RouteConfig};
+//!
+//! let server = DummyServer::builder()
+//!     .code(404)
+//!     .route(RouteConfig::new("/users/:id").body("user {{ request.params.id }}"))
+//!     .start()
+//!     .unwrap();
+//!
+//! let resp = reqwest::blocking::get(format!("{}/users/1", server.url())).unwrap();
+//! assert_eq!(resp.text().unwrap(), "user 1");
+//! assert_eq!(server.requests().len(), 1);
+//! ```
+//!
+//! The server shuts down once it is dropped.
+
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+
+use axum::{
+    body::{boxed, Body, Bytes},
+    extract::{ws::WebSocketUpgrade, ConnectInfo},
+    http::{HeaderMap, HeaderValue, Method, Request, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+
+#[cfg(feature = "tls")]
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use chrono::Local;
+use clap::crate_version;
+use colored::*;
+use colored_json::ToColoredJson;
+use futures_util::future::{self, BoxFuture, FutureExt, TryFutureExt};
+use hyper::header::{CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY};
+use inflector::Inflector;
+
+use crate::admin::ADMIN_PREFIX;
+use crate::chaos::{ChaosAcceptor, Faults, InjectedFault};
+use crate::compression::Compression;
+use crate::journal::Journal;
+use crate::listen::{Listen, ListenAddr, Listener};
+use crate::logging::{LogFormat, LogRecord};
+use crate::matching::IncomingRequest;
+use crate::metrics::{InjectedDelay, Metrics};
+use crate::peer::Peer;
+use crate::proxy::Proxy;
+use crate::ready::Ready;
+use crate::recording::{RecordedRequest, Recorder, Replay};
+use crate::reload::Reloader;
+use crate::routes::{
+    ActiveRoutes, ResponseBody, ResponseTemplate, RouteMatch, RouteSet, RouteTable,
+};
+use crate::scenarios::Scenarios;
+#[cfg(feature = "tls")]
+use crate::self_signed::SelfSigned;
+use crate::shutdown::{DrainAcceptor, Shutdown};
+use crate::summary::{FailedRequest, Summary, UnmatchedRequest};
+use crate::template::RequestContext;
+#[cfg(feature = "tls")]
+use crate::tls::ClientCertAcceptor;
+use crate::tls::ClientCertificate;
+
+mod admin;
+mod args;
+mod chaos;
+mod compression;
+mod config;
+mod delay;
+mod files;
+mod journal;
+mod listen;
+mod logging;
+mod matching;
+mod metrics;
+mod openapi;
+mod peer;
+mod proxy;
+mod ready;
+mod recording;
+mod reload;
+mod routes;
+mod scenarios;
+#[cfg(feature = "tls")]
+mod self_signed;
+mod server;
+mod shutdown;
+mod sse;
+mod streaming;
+mod summary;
+mod template;
+mod tls;
+mod websocket;
+
+pub use crate::args::Args;
+pub use crate::config::RouteConfig;
+pub use crate::journal::JournalEntry;
+pub use crate::server::{DummyServer, DummyServerBuilder};
+
+/// The body that was rendered for a response, kept around for logging
+#[derive(Debug, Clone)]
+struct RenderedBody(Bytes);
+
+/// The body as sent after compression, kept around for faults that tamper with it
+#[derive(Debug, Clone)]
+struct SentBody(Bytes);
+
+/// Why no route matched a request that got the default response, kept around for logging
+#[derive(Debug, Clone)]
+struct Unmatched(String);
+
+/// Why a response isn't the one configured, kept around for the shutdown summary
+#[derive(Debug, Clone)]
+struct Failure(String);
+
+/// Name of the route that answered a request, kept around for the metrics and shutdown summary
+///
+/// This is `default` for the default response and `serve-dir`, `replay` or `proxy` for requests
+/// answered by those.
+#[derive(Debug, Clone)]
+struct ServedBy(String);
+
+/// Note which route answered with `resp`
+fn served_by(mut resp: Response, route: &str) -> Response {
+    resp.extensions_mut().insert(ServedBy(route.to_string()));
+    resp
+}
+
+/// Answer with `status` because something went wrong
+fn failure_response(status: StatusCode, message: String) -> Response {
+    let mut resp = (status, message.clone()).into_response();
+    resp.extensions_mut().insert(Failure(message));
+    resp
+}
+
+/// Answer a request with the response of the first matching route
+///
+/// Requests not matching any route are answered from the `--serve-dir` directory, the
+/// `--replay` file or forwarded to the `--proxy-to` upstream if given and get the default
+/// response otherwise.
+#[allow(clippy::too_many_arguments)]
+async fn dummy_response(
+    method: Method,
+    uri: Uri,
+    req_headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    Extension(args): Extension<Args>,
+    Extension(routes): Extension<ActiveRoutes>,
+    Extension(scenarios): Extension<Scenarios>,
+    replay: Option<Extension<Arc<Replay>>>,
+    proxy: Option<Extension<Arc<Proxy>>>,
+    client_cert: Option<Extension<Option<ClientCertificate>>>,
+    ws: Option<WebSocketUpgrade>,
+    body: Bytes,
+) -> Response {
+    let routes = routes.current();
+    let request = IncomingRequest {
+        method: &method,
+        uri: &uri,
+        headers: &req_headers,
+        body: &body,
+    };
+    let mut unmatched = None;
+    let route_match = match routes.find(&request, &scenarios) {
+        Ok(route_match) => route_match,
+        Err(reason) => {
+            if let Some(dir) = &args.serve_dir {
+                if let Some((path, content)) = files::find_file(dir, uri.path()).await {
+                    let mut headers = HeaderMap::new();
+                    if let Some(content_type) = files::guess_content_type(&path) {
+                        headers.insert(CONTENT_TYPE, content_type);
+                    }
+                    return served_by(
+                        passthrough_response((StatusCode::OK, headers, content), &routes),
+                        "serve-dir",
+                    );
+                }
+            }
+            if let Some(Extension(replay)) = replay {
+                let request = RecordedRequest::new(&method, &uri, &req_headers, &body);
+                if let Some(recorded) = replay.find(&request) {
+                    let resp = match recorded.to_parts() {
+                        Ok(parts) => passthrough_response(parts, &routes),
+                        Err(e) => {
+                            failure_response(StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}"))
+                        }
+                    };
+                    return served_by(resp, "replay");
+                }
+            }
+            if let Some(Extension(proxy)) = proxy {
+                let resp = match proxy.forward(&method, &uri, &req_headers, body).await {
+                    Ok(parts) => passthrough_response(parts, &routes),
+                    Err(e) => failure_response(StatusCode::BAD_GATEWAY, format!("{e:#}")),
+                };
+                return served_by(resp, "proxy");
+            }
+            if !routes.is_empty() {
+                unmatched = Some(Unmatched(reason));
+            }
+            RouteMatch {
+                response: routes.default_response(),
+                params: Default::default(),
+                validator: None,
+                route: None,
+                transition: None,
+            }
+        }
+    };
+    let route = route_match.route.unwrap_or("default");
+    if let Some(validator) = route_match.validator {
+        if let Err(e) = validator.validate(&request, &route_match.params) {
+            return served_by(
+                failure_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("Request validation failed: {e}"),
+                ),
+                route,
+            );
+        }
+    }
+    let client_cert = client_cert.and_then(|Extension(client_cert)| client_cert);
+    let context = RequestContext::new(
+        &method,
+        &uri,
+        &req_headers,
+        &body,
+        peer,
+        route_match.params,
+        client_cert,
+    )
+    .into_tera_context();
+    let mut resp = respond(
+        route_match.response,
+        context,
+        args,
+        peer,
+        uri,
+        req_headers,
+        ws,
+    )
+    .await;
+    if let Some(unmatched) = unmatched {
+        resp.extensions_mut().insert(unmatched);
+    }
+    // Dropping the transition instead moves the scenario back.
+    if let Some(transition) = route_match.transition {
+        if resp.extensions().get::<Failure>().is_none() {
+            transition.commit();
+        }
+    }
+    served_by(resp, route)
+}
+
+/// Answer with `response`, rendering its templates with `context`
+async fn respond(
+    response: &ResponseTemplate,
+    context: tera::Context,
+    args: Args,
+    peer: Peer,
+    uri: Uri,
+    req_headers: HeaderMap,
+    ws: Option<WebSocketUpgrade>,
+) -> Response {
+    if let Some(script) = &response.websocket {
+        let ws = match ws {
+            Some(ws) => ws,
+            None => {
+                return failure_response(
+                    StatusCode::UPGRADE_REQUIRED,
+                    "This route expects a WebSocket upgrade".to_string(),
+                )
+            }
+        };
+        let script = script.clone();
+        return ws.on_upgrade(move |socket| async move {
+            websocket::Connection::new(socket, args, peer, uri.to_string(), context)
+                .run(&script)
+                .await
+        });
+    }
+
+    let mut headers = response.headers.clone();
+
+    // Manually insert a Date header here so that our log print will catch it later on as the
+    // date is inserted _after_ logging otherwise.
+    let time = Local::now();
+    headers.insert("date", HeaderValue::from_str(&time.to_rfc2822()).unwrap());
+
+    // Render body as Tera template with the request available as `request`.
+    let rendered_body = match &response.body {
+        ResponseBody::Template(template) => match template::render(template, &context) {
+            Ok(rendered_body) => Bytes::from(rendered_body),
+            Err(e) => {
+                return failure_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to render body template: {e:?}"),
+                )
+            }
+        },
+        ResponseBody::Static(body) => body.clone(),
+    };
+
+    let delay = response.delay.as_ref().map(|delay| delay.sample());
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+
+    // Event streams are sent as they are produced and thus never compressed.
+    let encoding = match &response.sse {
+        Some(_) => None,
+        None if rendered_body.is_empty() || headers.contains_key(CONTENT_ENCODING) => None,
+        None => {
+            if response.compression == Compression::Auto {
+                headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+            }
+            response.compression.pick(&req_headers)
+        }
+    };
+    let sent_body = match encoding {
+        Some(encoding) => match encoding.encode(&rendered_body) {
+            Ok(encoded) => {
+                headers.insert(CONTENT_ENCODING, compression::header_value(encoding));
+                encoded
+            }
+            Err(e) => {
+                return failure_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to compress body: {e}"),
+                )
+            }
+        },
+        None => rendered_body.clone(),
+    };
+    if let Some(fake_content_encoding) = &response.fake_content_encoding {
+        headers.insert(CONTENT_ENCODING, fake_content_encoding.clone());
+    }
+
+    let body = match (&response.sse, &response.transfer) {
+        (Some(script), _) => {
+            headers
+                .entry(CONTENT_TYPE)
+                .or_insert(HeaderValue::from_static("text/event-stream"));
+            headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+            let last_event_id = req_headers
+                .get("last-event-id")
+                .and_then(|id| id.to_str().ok())
+                .map(str::to_string);
+            script.clone().stream(context, last_event_id)
+        }
+        (None, Some(transfer)) => {
+            if transfer.has_content_length() {
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(sent_body.len()));
+            }
+            transfer.stream(sent_body.clone())
+        }
+        (None, None) => Body::from(sent_body.clone()),
+    };
+
+    let mut resp = (response.status, headers, boxed(body)).into_response();
+    resp.extensions_mut().insert(RenderedBody(rendered_body));
+    resp.extensions_mut().insert(SentBody(sent_body));
+    if let Some(delay) = delay {
+        resp.extensions_mut().insert(InjectedDelay(delay));
+    }
+    if !response.faults.is_empty() {
+        resp.extensions_mut()
+            .insert(Faults(response.faults.clone()));
+    }
+    resp
+}
+
+/// Send a response from upstream or a recording as is
+fn passthrough_response(
+    (status, headers, body): (StatusCode, HeaderMap, Bytes),
+    routes: &RouteTable,
+) -> Response {
+    let mut resp = (status, headers, body.clone()).into_response();
+    resp.extensions_mut().insert(RenderedBody(body.clone()));
+    resp.extensions_mut().insert(SentBody(body));
+    let faults = &routes.default_response().faults;
+    if !faults.is_empty() {
+        resp.extensions_mut().insert(Faults(faults.clone()));
+    }
+    resp
+}
+
+async fn print_request_response(
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let args = req.extensions().get::<Args>().unwrap().clone();
+    let journal = req.extensions().get::<Journal>().unwrap().clone();
+    let metrics = req.extensions().get::<Metrics>().unwrap().clone();
+    let summary = req.extensions().get::<Summary>().unwrap().clone();
+    let ConnectInfo(peer_info) = *req.extensions().get::<ConnectInfo<Peer>>().unwrap();
+    let method = req.method().to_string();
+    let uri = req.uri().to_string();
+    let http_version = format!("{:?}", req.version())
+        .split('/')
+        .nth(1)
+        .unwrap_or("unknown")
+        .to_string();
+    let req_headers = req.headers().clone();
+    let client_cert = tls::client_certificate(req.extensions());
+
+    let (parts, body) = req.into_parts();
+    let bytes = buffer_and_print("request", body).await?;
+    let bytes2 = bytes.clone();
+    let req = Request::from_parts(parts, Body::from(bytes));
+
+    // Compressed request bodies are logged decompressed, but only decoded if they are logged.
+    let logged_body =
+        (args.verbose >= 2 && !args.quiet).then(|| compression::decode(&req_headers, &bytes2));
+
+    let path = req.uri().path().to_string();
+    let query = req.uri().query().map(|query| query.to_string());
+
+    let resp = next.run(req).await;
+
+    if !path.starts_with(ADMIN_PREFIX) {
+        let route = resp
+            .extensions()
+            .get::<ServedBy>()
+            .map_or("default", |ServedBy(route)| route.as_str());
+        metrics.observe_request(&method, route, resp.status().as_u16(), bytes2.len());
+        if let Some(InjectedDelay(delay)) = resp.extensions().get::<InjectedDelay>() {
+            metrics.observe_delay(*delay);
+        }
+        summary.record_request(route);
+        if let Some(Unmatched(reason)) = resp.extensions().get::<Unmatched>() {
+            summary.record_unmatched(UnmatchedRequest {
+                method: method.clone(),
+                uri: uri.clone(),
+                reason: reason.clone(),
+            });
+        }
+        if let Some(Failure(message)) = resp.extensions().get::<Failure>() {
+            summary.record_error(FailedRequest {
+                method: method.clone(),
+                uri: uri.clone(),
+                status: resp.status().as_u16(),
+                message: message.clone(),
+            });
+        }
+        journal.record(JournalEntry {
+            timestamp: Local::now().to_rfc3339(),
+            peer: peer_info.to_string(),
+            method: method.clone(),
+            uri: uri.clone(),
+            path,
+            query,
+            headers: template::header_map(&req_headers),
+            body: String::from_utf8_lossy(&bytes2).to_string(),
+            status: resp.status().as_u16(),
+        });
+    }
+
+    if args.log_format != LogFormat::Pretty {
+        if !args.quiet {
+            let body = |bytes: &[u8]| {
+                (args.verbose >= 2).then(|| String::from_utf8_lossy(bytes).to_string())
+            };
+            let record = LogRecord {
+                timestamp: Local::now().to_rfc3339(),
+                peer: peer_info.to_string(),
+                method,
+                uri,
+                version: http_version,
+                status: resp.status().as_u16(),
+                fault: resp
+                    .extensions()
+                    .get::<InjectedFault>()
+                    .map(|fault| fault.0.to_string()),
+                unmatched: resp
+                    .extensions()
+                    .get::<Unmatched>()
+                    .map(|unmatched| unmatched.0.clone()),
+                client_cert: client_cert.clone(),
+                request_headers: template::header_map(&req_headers),
+                response_headers: template::header_map(resp.headers()),
+                request_body: logged_body.as_ref().map(|decoded| {
+                    let mut body = String::from_utf8_lossy(&decoded.body).to_string();
+                    if decoded.truncated {
+                        body.push_str(compression::TRUNCATED_MARKER);
+                    }
+                    body
+                }),
+                response_body: resp
+                    .extensions()
+                    .get::<RenderedBody>()
+                    .and_then(|rendered| body(&rendered.0)),
+            };
+            match args.log_format {
+                LogFormat::Json => println!("{}", record.to_json()),
+                LogFormat::Logfmt => println!("{}", record.to_logfmt()),
+                LogFormat::Pretty => unreachable!(),
+            }
+        }
+        return Ok(resp);
+    }
+
+    let time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let fault = resp
+        .extensions()
+        .get::<InjectedFault>()
+        .map(|fault| format!(" {} {}", "fault:".red(), fault.0.to_string().red().bold()))
+        .unwrap_or_default();
+
+    let unmatched = resp
+        .extensions()
+        .get::<Unmatched>()
+        .map(|unmatched| format!(" {} {}", "unmatched:".yellow(), unmatched.0))
+        .unwrap_or_default();
+
+    let client = client_cert
+        .as_ref()
+        .map(|cert| format!(" {} {}", "client:".magenta(), cert.subject))
+        .unwrap_or_default();
+
+    let connect_line = format!(
+        "{time} {peer_info}{client} {method} {uri} {status} {http}/{version}{fault}{unmatched}",
+        time = time.yellow(),
+        peer_info = peer_info.to_string().bold(),
+        client = client,
+        method = method.green(),
+        uri = uri.cyan().underline(),
+        status = resp.status().as_u16(),
+        http = "HTTP".blue(),
+        version = http_version.blue(),
+        fault = fault,
+        unmatched = unmatched,
+    );
+    if args.verbose >= 1 {
+        let method_path_version_line = format!(
+            "{method} {uri} {http}/{version}",
+            method = method.green(),
+            uri = uri.cyan().underline(),
+            http = "HTTP".blue(),
+            version = http_version.blue(),
+        );
+        let mut incoming_headers_vec = vec![];
+        for (hk, hv) in &req_headers {
+            incoming_headers_vec.push(format!(
+                "{deco} {key}: {value}",
+                deco = "│".green().bold(),
+                key = Inflector::to_train_case(hk.as_str()).cyan(),
+                value = hv.to_str().unwrap_or("<unprintable>")
+            ));
+        }
+        incoming_headers_vec.sort();
+        if !incoming_headers_vec.is_empty() {
+            incoming_headers_vec.insert(0, "".to_string());
+        }
+        let incoming_headers = incoming_headers_vec.join("\n");
+
+        let (body, truncated) = match &logged_body {
+            Some(decoded) => (String::from_utf8_lossy(&decoded.body), decoded.truncated),
+            None => (Default::default(), false),
+        };
+        let req_body_text = if body.is_empty() || args.verbose < 2 {
+            "".to_string()
+        } else {
+            let body_formatted = if truncated {
+                format!("{}{}", body, compression::TRUNCATED_MARKER)
+            } else if let Some(content_type) = req_headers.get(CONTENT_TYPE) {
+                if content_type == "application/json" {
+                    serde_json::from_str::<serde_json::Value>(&body)
+                        .and_then(|loaded_json| serde_json::to_string_pretty(&loaded_json))
+                        .and_then(|pretty_json| pretty_json.to_colored_json_auto())
+                        .unwrap()
+                } else {
+                    body.to_string()
+                }
+            } else {
+                body.to_string()
+            };
+            let body_formatted = body_formatted
+                .lines()
+                .map(|line| format!("{deco} {line}", deco = "│".green().bold(), line = line))
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!(
+                "\n{deco} {body}\n{body_formatted}",
+                deco = "│".green().bold(),
+                body = "Body:".yellow(),
+                body_formatted = body_formatted,
+            )
+        };
+
+        let client_cert_text = match &client_cert {
+            Some(cert) => format!(
+                "\n{deco} {label}\n{deco} Subject: {subject}\n{deco} SANs: {sans}\n{deco} SHA-256: {fingerprint}",
+                deco = "│".green().bold(),
+                label = "Client certificate:".yellow(),
+                subject = cert.subject,
+                sans = cert.sans.join(", "),
+                fingerprint = cert.fingerprint,
+            ),
+            None => "".to_string(),
+        };
+
+        let req_info = format!(
+            "{deco} {method_path_version_line}{headers}{client_cert_text}{req_body_text}",
+            deco = "│".green().bold(),
+            method_path_version_line = method_path_version_line,
+            headers = incoming_headers,
+            client_cert_text = client_cert_text,
+            req_body_text = req_body_text,
+        );
+
+        let status_line = format!(
+            "{http}/{version} {status_code} {status_text}",
+            http = "HTTP".blue(),
+            version = http_version.blue(),
+            status_code = resp.status().as_u16().to_string().blue(),
+            status_text = resp.status().canonical_reason().unwrap_or("").cyan(),
+        );
+
+        let mut outgoing_headers_vec = vec![];
+        for (hk, hv) in resp.headers() {
+            outgoing_headers_vec.push(format!(
+                "{deco} {key}: {value}",
+                deco = "│".red().bold(),
+                key = Inflector::to_train_case(hk.as_str()).cyan(),
+                value = hv.to_str().unwrap_or("<unprintable>")
+            ));
+        }
+        if !outgoing_headers_vec.is_empty() {
+            outgoing_headers_vec.insert(0, "".to_string());
+        }
+        outgoing_headers_vec.sort();
+        let outgoing_headers = outgoing_headers_vec.join("\n");
+
+        let resp_body = resp
+            .extensions()
+            .get::<RenderedBody>()
+            .map(|body| String::from_utf8_lossy(&body.0).to_string())
+            .unwrap_or_default();
+        let resp_body_text = if resp_body.is_empty() || args.verbose < 2 {
+            "".to_string()
+        } else {
+            let body_formatted = resp_body
+                .lines()
+                .map(|line| format!("{deco} {line}", deco = "│".red().bold(), line = line))
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!(
+                "\n{deco} {body}\n{body_formatted}",
+                deco = "│".red().bold(),
+                body = "Body:".yellow(),
+                body_formatted = body_formatted,
+            )
+        };
+
+        let resp_info = format!(
+            "{deco} {status_line}{headers}{resp_body_text}",
+            deco = "│".red().bold(),
+            status_line = status_line,
+            headers = outgoing_headers,
+            resp_body_text = resp_body_text,
+        );
+
+        println!(
+            "{connect_line}\n{req_banner}\n{req_info}\n{resp_banner}\n{resp_info}",
+            req_banner = "┌─Incoming request".green().bold(),
+            req_info = req_info,
+            resp_banner = "┌─Outgoing response".red().bold(),
+            resp_info = resp_info,
+        );
+    } else if !args.quiet {
+        println!("{connect_line}",);
+    }
+
+    Ok(resp)
+}
+
+async fn buffer_and_print<B>(direction: &str, body: B) -> Result<Bytes, (StatusCode, String)>
+where
+    B: axum::body::HttpBody<Data = Bytes>,
+    B::Error: std::fmt::Display,
+{
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("failed to read {direction} body: {err}"),
+            ));
+        }
+    };
+
+    Ok(bytes)
+}
+
+/// A server that is all set up and only needs to be awaited
+pub(crate) struct PreparedServer {
+    pub journal: Journal,
+    pub reloader: Reloader,
+    /// The certificate generated for --tls-self-signed
+    #[cfg(feature = "tls")]
+    pub self_signed: Option<SelfSigned>,
+    pub serve: BoxFuture<'static, io::Result<()>>,
+    pub shutdown: Shutdown,
+    pub summary: Summary,
+}
+
+/// Set up everything needed to serve requests for the given args on `listeners`
+///
+/// Routes in `extra_routes` are matched after the ones from the `--config` file. Everything
+/// that can fail happens here so that errors surface before we start serving. The files the
+/// routes and TLS certificate came from are watched and reloaded on changes.
+pub(crate) async fn prepare(
+    args: &Args,
+    listeners: Vec<(Listen, Listener)>,
+    extra_routes: Vec<RouteConfig>,
+    handle: Handle,
+) -> Result<PreparedServer> {
+    let metrics = Metrics::new()?;
+    let journal = Journal::new(args.journal_size);
+    let summary = Summary::default();
+    let shutdown = Shutdown::new(handle.clone());
+    let replay = args
+        .replay
+        .as_deref()
+        .map(Replay::from_file)
+        .transpose()?
+        .map(Arc::new);
+    let proxy = match &args.proxy_to {
+        Some(upstream) => {
+            let recorder = args.record.as_deref().map(Recorder::new).transpose()?;
+            Some(Arc::new(Proxy::new(upstream, recorder, args.quiet)?))
+        }
+        None => None,
+    };
+
+    // Listeners serving the same config share their routes and scenarios.
+    let mut route_sets: Vec<RouteSet> = vec![];
+    let mut apps = vec![];
+    for (listen, _) in &listeners {
+        let config = listen.config.clone().or_else(|| args.config.clone());
+        let index = match route_sets
+            .iter()
+            .position(|route_set| route_set.args.config == config)
+        {
+            Some(index) => index,
+            None => {
+                let route_set_args = Args {
+                    config,
+                    ..args.clone()
+                };
+                route_sets.push(RouteSet::new(&route_set_args, extra_routes.clone())?);
+                route_sets.len() - 1
+            }
+        };
+        let route_set = &route_sets[index];
+
+        let mut app = Router::new()
+            .nest(ADMIN_PREFIX, admin::router())
+            .fallback(dummy_response)
+            .layer(middleware::from_fn(chaos::inject_faults))
+            .layer(middleware::from_fn(print_request_response))
+            .layer(Extension(journal.clone()))
+            .layer(Extension(summary.clone()))
+            .layer(Extension(route_set.scenarios.clone()))
+            .layer(Extension(metrics.clone()))
+            .layer(Extension(route_set.routes.clone()))
+            .layer(Extension(route_set.args.clone()))
+            .layer(middleware::from_fn(shutdown::mark_busy));
+        if let Some(replay) = &replay {
+            app = app.layer(Extension(replay.clone()));
+        }
+        if let Some(proxy) = &proxy {
+            app = app.layer(Extension(proxy.clone()));
+        }
+        apps.push(app);
+    }
+    let reloader = Reloader::new(args, route_sets);
+
+    // Bind the metrics port right away so that we fail early if it is taken. It has a handle of
+    // its own so that scrapers keeping their connection open don't hold up the shutdown, and is
+    // stopped once everything else is.
+    let metrics_handle = Handle::new();
+    if let Some(metrics_port) = args.metrics_port {
+        let metrics_addr = SocketAddr::from((args.interface, metrics_port));
+        let metrics_listener = TcpListener::bind(metrics_addr)
+            .with_context(|| format!("Failed to bind metrics port {metrics_addr}"))?;
+        let metrics_app = Router::new()
+            .route("/metrics", get(admin::metrics))
+            .layer(Extension(metrics.clone()));
+        tokio::spawn(
+            axum_server::from_tcp(metrics_listener)
+                .handle(metrics_handle.clone())
+                .serve(metrics_app.into_make_service()),
+        );
+    }
+
+    // configure certificate and private key used by https
+    #[cfg(feature = "tls")]
+    let (reloader, self_signed, tls_config) = {
+        let tls_listeners = listeners
+            .iter()
+            .filter(|(listen, _)| listen.tls)
+            .map(|(listen, _)| listen)
+            .collect::<Vec<_>>();
+        if let Some(listen) = tls_listeners.first() {
+            if !args.tls() {
+                bail!(
+                    "Listener {} uses TLS but neither --tls-cert nor --tls-self-signed is given",
+                    listen.addr
+                );
+            }
+        }
+
+        let self_signed = if args.tls_self_signed && !tls_listeners.is_empty() {
+            let mut sans = args.tls_san.clone();
+            for listen in &tls_listeners {
+                if let ListenAddr::Tcp(addr) = listen.addr {
+                    if !addr.ip().is_unspecified() {
+                        sans.push(addr.ip().to_string());
+                    }
+                }
+            }
+            let certificate = SelfSigned::generate(&sans)?;
+            if let Some(path) = &args.tls_write_ca {
+                std::fs::write(path, &certificate.ca_pem).with_context(|| {
+                    format!("Failed to write CA certificate to '{}'", path.display())
+                })?;
+            }
+            Some(certificate)
+        } else {
+            None
+        };
+        match tls_listeners.is_empty() {
+            true => (reloader, self_signed, None),
+            false => {
+                let tls_config =
+                    RustlsConfig::from_config(tls::server_config(args, self_signed.as_ref())?);
+                let reloader = reloader.with_tls_config(tls_config.clone(), self_signed.clone());
+                (reloader, self_signed, Some(tls_config))
+            }
+        }
+    };
+    #[cfg(not(feature = "tls"))]
+    if let Some((listen, _)) = listeners.iter().find(|(listen, _)| listen.tls) {
+        bail!(
+            "Listener {} uses TLS but dummyhttp was built without TLS support",
+            listen.addr
+        );
+    }
+
+    tokio::spawn(reloader.clone().watch());
+
+    let serves = listeners
+        .into_iter()
+        .zip(apps)
+        .map(|((listen, listener), app)| {
+            let make_service = app.into_make_service_with_connect_info::<Peer>();
+            let listener = match listener {
+                Listener::Tcp(listener) => listener,
+                #[cfg(unix)]
+                Listener::Unix(listener) => {
+                    let (metrics, shutdown, quiet) =
+                        (metrics.clone(), shutdown.clone(), args.quiet);
+                    return async move {
+                        listen::serve_unix(listener, make_service, metrics, &shutdown, quiet).await
+                    }
+                    .boxed();
+                }
+            };
+            #[cfg(feature = "tls")]
+            if let (true, Some(tls_config)) = (listen.tls, &tls_config) {
+                return axum_server::from_tcp_rustls(listener, tls_config.clone())
+                    .map(|tls| {
+                        ClientCertAcceptor::new(
+                            tls.acceptor(
+                                ChaosAcceptor::new(metrics.clone())
+                                    .acceptor(DrainAcceptor::new(shutdown.clone())),
+                            ),
+                        )
+                    })
+                    .handle(handle.clone())
+                    .serve(make_service)
+                    .boxed();
+            }
+            #[cfg(not(feature = "tls"))]
+            let _ = listen;
+            axum_server::from_tcp(listener)
+                .acceptor(
+                    ChaosAcceptor::new(metrics.clone())
+                        .acceptor(DrainAcceptor::new(shutdown.clone())),
+                )
+                .handle(handle.clone())
+                .serve(make_service)
+                .boxed()
+        })
+        .collect::<Vec<_>>();
+    let serve = future::try_join_all(serves)
+        .map_ok(move |_| metrics_handle.shutdown())
+        .boxed();
+
+    Ok(PreparedServer {
+        journal,
+        reloader,
+        #[cfg(feature = "tls")]
+        self_signed,
+        serve,
+        shutdown,
+        summary,
+    })
+}
+
+/// Run dummyhttp as configured on the command line until it is killed
+pub async fn run(args: Args) -> Result<()> {
+    let listeners = args.listeners();
+    let socket_files = listeners
+        .iter()
+        .filter_map(|listen| match &listen.addr {
+            ListenAddr::Unix(path) => Some(path.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let listeners = listeners
+        .into_iter()
+        .map(|listen| listen.bind(&args))
+        .collect::<Result<Vec<_>>>()?;
+    let ready = Ready::new(listeners.iter().map(|(listen, _)| listen));
+    let urls = &ready.urls;
+    let server = prepare(&args, listeners, vec![], Handle::new()).await?;
+    for path in socket_files {
+        server.shutdown.remove_socket_file(path);
+    }
+    let shutdown_signal = shutdown_signal()?;
+
+    if let Some(format) = args.print_address {
+        println!("{}", ready.format(format));
+    } else if !args.quiet {
+        match args.log_format {
+            LogFormat::Pretty => println!(
+                "{}{} {} {}",
+                "dummyhttp v".bold(),
+                crate_version!().bold(),
+                "listening on".dimmed(),
+                urls.iter()
+                    .map(|url| url.bold().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            format => println!(
+                "{}",
+                logging::format_message(
+                    format,
+                    &format!(
+                        "dummyhttp v{} listening on {}",
+                        crate_version!(),
+                        urls.join(", ")
+                    )
+                )
+            ),
+        }
+    }
+    if !args.quiet {
+        #[cfg(feature = "tls")]
+        if let Some(self_signed) = &server.self_signed {
+            let message = format!(
+                "Using a self-signed certificate, CA SHA-256 fingerprint: {}",
+                self_signed.ca_fingerprint
+            );
+            match args.log_format {
+                LogFormat::Pretty => println!("{}", message.dimmed()),
+                format => println!("{}", logging::format_message(format, &message)),
+            }
+        }
+    }
+
+    // Reload on SIGHUP as well, e.g. for when the files are changed in a way that doesn't update
+    // their modification times.
+    #[cfg(unix)]
+    {
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .context("Failed to listen for SIGHUP")?;
+        let reloader = server.reloader.clone();
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                reloader.reload().await;
+            }
+        });
+    }
+
+    if let Some(path) = &args.ready_file {
+        ready.write(path)?;
+    }
+
+    let mut serve = server.serve;
+    tokio::select! {
+        result = &mut serve => result?,
+        signal = shutdown_signal => {
+            if !args.quiet {
+                let message = format!(
+                    "Received {signal}, waiting up to {:?} for open connections to finish",
+                    args.shutdown_timeout
+                );
+                match args.log_format {
+                    LogFormat::Pretty => println!("{}", message.dimmed()),
+                    format => println!("{}", logging::format_message(format, &message)),
+                }
+            }
+            server.shutdown.graceful(args.shutdown_timeout);
+            serve.await?;
+        }
+    }
+
+    let totals = server.summary.totals();
+    match &args.summary_file {
+        Some(path) => totals.write(path)?,
+        None if !args.quiet => println!("{}", logging::format_summary(args.log_format, &totals)),
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Listen for SIGINT and SIGTERM
+///
+/// The returned future resolves to the name of the first signal received. Listening starts right
+/// away so that signals sent before the future is polled aren't missed.
+#[cfg(unix)]
+fn shutdown_signal() -> Result<BoxFuture<'static, &'static str>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt()).context("Failed to listen for SIGINT")?;
+    let mut terminate = signal(SignalKind::terminate()).context("Failed to listen for SIGTERM")?;
+    Ok(async move {
+        tokio::select! {
+            _ = interrupt.recv() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        }
+    }
+    .boxed())
+}
+
+/// Listen for Ctrl-C
+#[cfg(not(unix))]
+fn shutdown_signal() -> Result<BoxFuture<'static, &'static str>> {
+    let mut ctrl_c = tokio::signal::windows::ctrl_c().context("Failed to listen for Ctrl-C")?;
+    Ok(async move {
+        ctrl_c.recv().await;
+        "Ctrl-C"
+    }
+    .boxed())
+}
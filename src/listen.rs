@@ -0,0 +1,403 @@
+use std::env;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use socket2::{Domain, Socket, Type};
+
+use crate::args::Args;
+
+/// Where and how to accept connections, as given via --listen
+///
+/// Format: `<address>[+tls][,config=<file>]`
+///
+/// 0.0.0.0:8080 - plain HTTP on all IPv4 interfaces
+/// [::]:8443+tls - HTTPS on all IPv6 interfaces
+/// unix:/tmp/dummyhttp.sock - plain HTTP on a Unix socket
+/// fd:3+tls - HTTPS on a listening socket inherited from the parent process
+/// 127.0.0.1:9090,config=admin.yaml - serve the routes of admin.yaml instead of --config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listen {
+    pub addr: ListenAddr,
+    pub tls: bool,
+
+    /// Route configuration served on this listener instead of --config
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+
+    /// An already bound socket inherited from the parent process
+    Fd(u32),
+}
+
+/// A bound listener, ready to be served
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+/// Owner of a Unix socket as given via --unix-socket-owner
+///
+/// Format: `<uid>[:<gid>]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnixOwner {
+    pub uid: u32,
+    pub gid: Option<u32>,
+}
+
+impl Listen {
+    /// Base URL of this listener (e.g. `https://[::1]:8443` or `http://unix:/tmp/dh.sock`)
+    ///
+    /// Clients can't connect to unspecified addresses, so listeners on all interfaces are
+    /// reported with the loopback address instead.
+    pub fn url(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        match self.addr {
+            ListenAddr::Tcp(addr) if addr.ip().is_unspecified() => {
+                let ip = match addr.ip() {
+                    IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                };
+                format!("{scheme}://{}", SocketAddr::new(ip, addr.port()))
+            }
+            _ => format!("{scheme}://{}", self.addr),
+        }
+    }
+
+    /// Bind the address of this listener
+    ///
+    /// IPv6 sockets only accept IPv6 connections so that the same port can be bound on an IPv4
+    /// address by another listener. Unix sockets get the permissions and owner given by
+    /// --unix-socket-mode and --unix-socket-owner. Inherited sockets are taken over as they are,
+    /// inherited Unix sockets can't speak HTTPS though.
+    /// The returned `Listen` has the address actually bound to, e.g. the port the OS picked for
+    /// port 0.
+    pub fn bind(&self, args: &Args) -> Result<(Listen, Listener)> {
+        let (addr, listener) = match &self.addr {
+            ListenAddr::Tcp(addr) => {
+                let listener = bind_tcp(*addr)?;
+                (
+                    ListenAddr::Tcp(listener.local_addr()?),
+                    Listener::Tcp(listener),
+                )
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => (self.addr.clone(), Listener::Unix(unix::bind(path, args)?)),
+            #[cfg(unix)]
+            ListenAddr::Fd(fd) => {
+                let (addr, listener) = unix::adopt(*fd)?;
+                if self.tls && matches!(listener, Listener::Unix(_)) {
+                    anyhow::bail!(
+                        "TLS is not supported on Unix sockets, but file descriptor {} is one",
+                        fd
+                    );
+                }
+                (addr, listener)
+            }
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) | ListenAddr::Fd(_) => {
+                let _ = args;
+                anyhow::bail!("Listener {} is not supported on this platform", self.addr)
+            }
+        };
+        Ok((
+            Listen {
+                addr,
+                ..self.clone()
+            },
+            listener,
+        ))
+    }
+}
+
+fn bind_tcp(addr: SocketAddr) -> Result<TcpListener> {
+    let bind = || -> std::io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(1024)?;
+        Ok(socket.into())
+    };
+    bind().with_context(|| format!("Failed to bind {addr}"))
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{addr}"),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+            ListenAddr::Fd(fd) => write!(f, "fd:{fd}"),
+        }
+    }
+}
+
+impl FromStr for Listen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Listen, String> {
+        let mut parts = s.split(',');
+        let addr = parts.next().unwrap_or_default().trim();
+        let (addr, tls) = match addr.strip_suffix("+tls") {
+            Some(addr) => (addr, true),
+            None => (addr, false),
+        };
+        let invalid = || {
+            format!(
+                "Invalid listen address '{}' (expected e.g. 0.0.0.0:8080, [::]:8443, unix:/tmp/dummyhttp.sock or fd:3)",
+                addr
+            )
+        };
+        let addr = if let Some(path) = addr.strip_prefix("unix:") {
+            if tls {
+                return Err("TLS is not supported on Unix sockets".to_string());
+            }
+            if path.is_empty() {
+                return Err(invalid());
+            }
+            ListenAddr::Unix(PathBuf::from(path))
+        } else if let Some(fd) = addr.strip_prefix("fd:") {
+            ListenAddr::Fd(fd.parse().map_err(|_| invalid())?)
+        } else {
+            ListenAddr::Tcp(addr.parse().map_err(|_| invalid())?)
+        };
+
+        let mut config = None;
+        for option in parts {
+            match option.trim().split_once('=') {
+                Some(("config", path)) if !path.is_empty() => config = Some(PathBuf::from(path)),
+                _ => return Err(format!("Unknown listen option '{}'", option)),
+            }
+        }
+
+        Ok(Listen { addr, tls, config })
+    }
+}
+
+impl FromStr for UnixOwner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<UnixOwner, String> {
+        let parse_id = |id: &str| {
+            id.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid owner '{}' (expected <uid>[:<gid>])", s))
+        };
+        match s.split_once(':') {
+            Some((uid, gid)) => Ok(UnixOwner {
+                uid: parse_id(uid)?,
+                gid: Some(parse_id(gid)?),
+            }),
+            None => Ok(UnixOwner {
+                uid: parse_id(s)?,
+                gid: None,
+            }),
+        }
+    }
+}
+
+/// Parse the permissions of a Unix socket given in octal (e.g. `660`)
+pub fn parse_mode(src: &str) -> Result<u32, String> {
+    u32::from_str_radix(src, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("Invalid mode '{}' (expected octal, e.g. 660)", src))
+}
+
+/// File descriptors passed via systemd socket activation, see sd_listen_fds(3)
+pub fn systemd_fds() -> Vec<u32> {
+    const SD_LISTEN_FDS_START: u32 = 3;
+
+    let var = |name| {
+        env::var(name)
+            .ok()
+            .and_then(|value| value.parse::<u32>().ok())
+    };
+    match (var("LISTEN_PID"), var("LISTEN_FDS")) {
+        (Some(pid), Some(count)) if pid == std::process::id() => {
+            (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count).collect()
+        }
+        _ => vec![],
+    }
+}
+
+#[cfg(unix)]
+pub use self::unix::serve as serve_unix;
+
+#[cfg(unix)]
+mod unix {
+    use std::convert::TryFrom;
+    use std::fs::{self, Permissions};
+    use std::io;
+    use std::net::TcpListener;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::time::Duration;
+
+    use anyhow::{anyhow, bail, Context, Result};
+    use axum::extract::connect_info::IntoMakeServiceWithConnectInfo;
+    use axum::Router;
+    use axum_server::accept::Accept;
+    use hyper::server::conn::Http;
+    use socket2::{Domain, Socket, Type};
+    use tokio::sync::mpsc;
+    use tower::Service;
+
+    use super::{ListenAddr, Listener};
+    use crate::args::Args;
+    use crate::chaos::ChaosAcceptor;
+    use crate::metrics::Metrics;
+    use crate::peer::Peer;
+    use crate::shutdown::{DrainAcceptor, Shutdown};
+
+    /// Bind a Unix socket at `path`, replacing a stale socket left behind by a previous run
+    pub fn bind(path: &Path, args: &Args) -> Result<UnixListener> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                bail!("'{}' exists and is not a socket", path.display());
+            }
+            // Only remove the socket if nobody is listening on it anymore.
+            if UnixStream::connect(path).is_ok() {
+                bail!("Another process is listening on '{}'", path.display());
+            }
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket '{}'", path.display()))?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind '{}'", path.display()))?;
+        if let Some(mode) = args.unix_socket_mode {
+            fs::set_permissions(path, Permissions::from_mode(mode))
+                .with_context(|| format!("Failed to set permissions of '{}'", path.display()))?;
+        }
+        if let Some(owner) = args.unix_socket_owner {
+            std::os::unix::fs::chown(path, Some(owner.uid), owner.gid)
+                .with_context(|| format!("Failed to change owner of '{}'", path.display()))?;
+        }
+        Ok(listener)
+    }
+
+    /// Take over the listening TCP or Unix socket `fd` inherited from the parent process
+    ///
+    /// Sockets which are bound but not yet listening are put into listening state. Descriptors
+    /// which turn out not to be usable are left open.
+    pub fn adopt(fd: u32) -> Result<(ListenAddr, Listener)> {
+        let raw = RawFd::try_from(fd).map_err(|_| anyhow!("Invalid file descriptor {}", fd))?;
+        let socket = unsafe { Socket::from_raw_fd(raw) };
+        let inspect = || -> Result<bool> {
+            if socket.r#type()? != Type::STREAM {
+                bail!("not a stream socket");
+            }
+            let addr = socket.local_addr()?;
+            let is_tcp = addr.as_socket().is_some();
+            if !is_tcp && i32::from(addr.family()) != i32::from(Domain::UNIX) {
+                bail!("neither a TCP nor a Unix socket");
+            }
+            if !socket.is_listener()? {
+                socket.listen(1024)?;
+            }
+            socket.set_cloexec(true)?;
+            Ok(is_tcp)
+        };
+        let is_tcp = match inspect() {
+            Ok(is_tcp) => is_tcp,
+            Err(err) => {
+                let _ = socket.into_raw_fd();
+                return Err(err.context(format!("Can't listen on file descriptor {}", fd)));
+            }
+        };
+
+        if is_tcp {
+            let listener = TcpListener::from(socket);
+            Ok((
+                ListenAddr::Tcp(listener.local_addr()?),
+                Listener::Tcp(listener),
+            ))
+        } else {
+            let listener = UnixListener::from(socket);
+            let addr = match listener.local_addr()?.as_pathname() {
+                Some(path) => ListenAddr::Unix(path.to_path_buf()),
+                None => ListenAddr::Fd(fd),
+            };
+            Ok((addr, Listener::Unix(listener)))
+        }
+    }
+
+    /// Serve `make_service` on a Unix socket until `shutdown` is requested
+    ///
+    /// axum-server only speaks TCP, so connections are handed to hyper directly after passing
+    /// through the same acceptors as TCP connections.
+    pub async fn serve(
+        listener: UnixListener,
+        mut make_service: IntoMakeServiceWithConnectInfo<Router, Peer>,
+        metrics: Metrics,
+        shutdown: &Shutdown,
+        quiet: bool,
+    ) -> io::Result<()> {
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::UnixListener::from_std(listener)?;
+        let acceptor = ChaosAcceptor::new(metrics).acceptor(DrainAcceptor::new(shutdown.clone()));
+        // Every connection holds a sender, so receiving fails once all of them are closed.
+        let (open_tx, mut open_rx) = mpsc::channel::<()>(1);
+        let timeout = loop {
+            let accepted = tokio::select! {
+                biased;
+                timeout = shutdown.requested() => break timeout,
+                accepted = listener.accept() => accepted,
+            };
+            let stream = match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    // Errors like running out of file descriptors are usually temporary, so we
+                    // wait a moment and keep going.
+                    if !quiet {
+                        eprintln!("Failed to accept connection: {e}");
+                    }
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let service = match make_service.call(&stream).await {
+                Ok(service) => service,
+                Err(infallible) => match infallible {},
+            };
+            let (stream, service) = match acceptor.accept(stream, service).await {
+                Ok(accepted) => accepted,
+                Err(_) => continue,
+            };
+            let open = open_tx.clone();
+            let drain = shutdown.requested();
+            tokio::spawn(async move {
+                let _open = open;
+                let connection = Http::new()
+                    .serve_connection(stream, service)
+                    .with_upgrades();
+                tokio::pin!(connection);
+                tokio::select! {
+                    _ = connection.as_mut() => return,
+                    _ = drain => {}
+                }
+                connection.as_mut().graceful_shutdown();
+                let _: Result<(), hyper::Error> = connection.await;
+            });
+        };
+
+        drop(listener);
+        drop(open_tx);
+        let _ = tokio::time::timeout(timeout, open_rx.recv()).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::Local;
+use clap::ArgEnum;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::summary::Totals;
+use crate::tls::ClientCertificate;
+
+/// How requests are logged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum LogFormat {
+    /// Colored, human readable output
+    Pretty,
+
+    /// One JSON object per line
+    Json,
+
+    /// One logfmt line (key=value pairs)
+    Logfmt,
+}
+
+/// A single request as logged in the machine readable formats
+#[derive(Debug, Serialize)]
+pub struct LogRecord {
+    pub timestamp: String,
+    pub peer: String,
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fault: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unmatched: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<ClientCertificate>,
+    pub request_headers: BTreeMap<String, String>,
+    pub response_headers: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_body: Option<String>,
+}
+
+impl LogRecord {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize log record")
+    }
+
+    /// Format as logfmt with headers flattened into `request_header.<name>` and
+    /// `response_header.<name>` keys and the client certificate into `client_cert.<field>` keys
+    pub fn to_logfmt(&self) -> String {
+        let mut pairs = vec![
+            ("timestamp".to_string(), self.timestamp.clone()),
+            ("peer".to_string(), self.peer.clone()),
+            ("method".to_string(), self.method.clone()),
+            ("uri".to_string(), self.uri.clone()),
+            ("version".to_string(), self.version.clone()),
+            ("status".to_string(), self.status.to_string()),
+        ];
+        if let Some(fault) = &self.fault {
+            pairs.push(("fault".to_string(), fault.clone()));
+        }
+        if let Some(unmatched) = &self.unmatched {
+            pairs.push(("unmatched".to_string(), unmatched.clone()));
+        }
+        if let Some(cert) = &self.client_cert {
+            pairs.push(("client_cert.subject".to_string(), cert.subject.clone()));
+            pairs.push(("client_cert.sans".to_string(), cert.sans.join(",")));
+            pairs.push((
+                "client_cert.fingerprint".to_string(),
+                cert.fingerprint.clone(),
+            ));
+        }
+        for (key, value) in &self.request_headers {
+            pairs.push((format!("request_header.{key}"), value.clone()));
+        }
+        for (key, value) in &self.response_headers {
+            pairs.push((format!("response_header.{key}"), value.clone()));
+        }
+        if let Some(body) = &self.request_body {
+            pairs.push(("request_body".to_string(), body.clone()));
+        }
+        if let Some(body) = &self.response_body {
+            pairs.push(("response_body".to_string(), body.clone()));
+        }
+
+        let mut line = String::new();
+        for (key, value) in pairs {
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            write!(line, "{}={}", key, logfmt_value(&value)).unwrap();
+        }
+        line
+    }
+}
+
+/// A WebSocket frame as logged in the machine readable formats
+#[derive(Debug, Serialize)]
+pub struct FrameRecord {
+    pub timestamp: String,
+    pub peer: String,
+    pub uri: String,
+
+    /// `received` or `sent`
+    pub direction: &'static str,
+
+    /// `text`, `binary`, `ping`, `pong` or `close`
+    pub frame: &'static str,
+
+    /// Text of the message, base64 for binary messages and `<code> <reason>` for close frames
+    pub data: String,
+}
+
+impl FrameRecord {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize log record")
+    }
+
+    pub fn to_logfmt(&self) -> String {
+        format!(
+            "timestamp={} peer={} uri={} direction={} frame={} data={}",
+            logfmt_value(&self.timestamp),
+            logfmt_value(&self.peer),
+            logfmt_value(&self.uri),
+            self.direction,
+            self.frame,
+            logfmt_value(&self.data)
+        )
+    }
+}
+
+/// Format a plain message (such as the startup line) as a log record
+pub fn format_message(format: LogFormat, message: &str) -> String {
+    let timestamp = Local::now().to_rfc3339();
+    match format {
+        LogFormat::Pretty => message.to_string(),
+        LogFormat::Json => json!({ "timestamp": timestamp, "message": message }).to_string(),
+        LogFormat::Logfmt => format!(
+            "timestamp={} message={}",
+            logfmt_value(&timestamp),
+            logfmt_value(message)
+        ),
+    }
+}
+
+/// Format the shutdown summary
+///
+/// JSON logs get the whole summary, the other formats a multi-line or single-line overview.
+pub fn format_summary(format: LogFormat, totals: &Totals) -> String {
+    match format {
+        LogFormat::Pretty => totals.to_pretty(),
+        LogFormat::Json => json!({
+            "timestamp": Local::now().to_rfc3339(),
+            "message": "Shutdown summary",
+            "summary": totals,
+        })
+        .to_string(),
+        LogFormat::Logfmt => format!(
+            "{} requests={} unmatched={} errors={}",
+            format_message(format, "Shutdown summary"),
+            totals.requests,
+            totals.unmatched.count,
+            totals.errors.count
+        ),
+    }
+}
+
+/// Quote a logfmt value if needed
+fn logfmt_value(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '=' || c == '"' || c.is_control());
+    if !needs_quotes {
+        return value.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,503 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{anyhow, bail, Context, Result};
+use axum::http::{HeaderMap, Method, Uri};
+use hyper::header::HeaderName;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::config::{
+    BodyMatchConfig, PathConfig, PathMatchConfig, RequestConfig, ValueConfig, ValueMatchConfig,
+};
+
+/// The parts of a request routes are matched against
+#[derive(Debug)]
+pub struct IncomingRequest<'a> {
+    pub method: &'a Method,
+    pub uri: &'a Uri,
+    pub headers: &'a HeaderMap,
+    pub body: &'a [u8],
+}
+
+/// Matches the request path
+#[derive(Debug)]
+pub enum PathMatcher {
+    Pattern(PathPattern),
+    Exact(String),
+    Prefix(String),
+
+    /// Used for regexes and globs
+    Regex(Regex),
+}
+
+/// Matches the query, headers and body of a request
+#[derive(Debug, Default)]
+pub struct RequestMatcher {
+    query: Vec<(String, ValueMatcher)>,
+    headers: Vec<(HeaderName, ValueMatcher)>,
+    body: Vec<BodyMatcher>,
+}
+
+/// Matches a query parameter or header
+#[derive(Debug)]
+pub enum ValueMatcher {
+    Equals(String),
+    Contains(String),
+    Regex(Regex),
+    Present(bool),
+}
+
+/// Matches the request body
+#[derive(Debug)]
+pub enum BodyMatcher {
+    Contains(String),
+    Regex(Regex),
+    JsonPath {
+        path: JsonPath,
+        equals: Option<Value>,
+    },
+    Json(Value),
+}
+
+impl PathMatcher {
+    pub fn from_config(config: &PathConfig) -> Result<PathMatcher> {
+        Ok(match config {
+            PathConfig::Pattern(pattern) => PathMatcher::Pattern(PathPattern::parse(pattern)?),
+            PathConfig::Matcher(PathMatchConfig::Exact(path)) => PathMatcher::Exact(path.clone()),
+            PathConfig::Matcher(PathMatchConfig::Prefix(path)) => PathMatcher::Prefix(path.clone()),
+            PathConfig::Matcher(PathMatchConfig::Regex(regex)) => PathMatcher::Regex(
+                Regex::new(regex).with_context(|| format!("Invalid path regex '{}'", regex))?,
+            ),
+            PathConfig::Matcher(PathMatchConfig::Glob(glob)) => PathMatcher::Regex(
+                Regex::new(&glob_to_regex(glob))
+                    .with_context(|| format!("Invalid path glob '{}'", glob))?,
+            ),
+        })
+    }
+
+    /// Match a request path, returning the captured parameters on success
+    pub fn matches(&self, path: &str) -> Option<BTreeMap<String, String>> {
+        match self {
+            PathMatcher::Pattern(pattern) => pattern.matches(path),
+            PathMatcher::Exact(exact) => (path == exact).then(BTreeMap::new),
+            PathMatcher::Prefix(prefix) => path.starts_with(prefix.as_str()).then(BTreeMap::new),
+            PathMatcher::Regex(regex) => {
+                let captures = regex.captures(path)?;
+                Some(
+                    regex
+                        .capture_names()
+                        .flatten()
+                        .filter_map(|name| {
+                            captures
+                                .name(name)
+                                .map(|value| (name.to_string(), value.as_str().to_string()))
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+/// Translate a glob into an anchored regex
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+impl RequestMatcher {
+    pub fn from_config(config: &RequestConfig) -> Result<RequestMatcher> {
+        let query = config
+            .query
+            .iter()
+            .map(|(key, value)| {
+                let matcher = ValueMatcher::from_config(value)
+                    .with_context(|| format!("Invalid matcher for query parameter '{}'", key))?;
+                Ok((key.clone(), matcher))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let headers = config
+            .headers
+            .iter()
+            .map(|(key, value)| {
+                let name = HeaderName::from_lowercase(key.to_lowercase().as_bytes())
+                    .with_context(|| format!("Invalid header name '{}'", key))?;
+                let matcher = ValueMatcher::from_config(value)
+                    .with_context(|| format!("Invalid matcher for header '{}'", key))?;
+                Ok((name, matcher))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let body = config
+            .body
+            .clone()
+            .map(|body| body.into_vec())
+            .unwrap_or_default()
+            .iter()
+            .map(BodyMatcher::from_config)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(RequestMatcher {
+            query,
+            headers,
+            body,
+        })
+    }
+
+    /// Check a request, returning why it doesn't match otherwise
+    pub fn check(&self, request: &IncomingRequest<'_>) -> Result<(), String> {
+        if !self.query.is_empty() {
+            let params: Vec<(String, String)> =
+                serde_urlencoded::from_str(request.uri.query().unwrap_or("")).unwrap_or_default();
+            for (key, matcher) in &self.query {
+                let values = params
+                    .iter()
+                    .filter(|(k, _)| k == key)
+                    .map(|(_, v)| v.as_str())
+                    .collect::<Vec<_>>();
+                if !matcher.matches(&values) {
+                    return Err(format!("query parameter '{}' {}", key, matcher.failure()));
+                }
+            }
+        }
+
+        for (name, matcher) in &self.headers {
+            let values = request
+                .headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect::<Vec<_>>();
+            if !matcher.matches(&values) {
+                return Err(format!("header '{}' {}", name, matcher.failure()));
+            }
+        }
+
+        if !self.body.is_empty() {
+            let body = String::from_utf8_lossy(request.body);
+            let json = serde_json::from_str::<Value>(&body).ok();
+            for matcher in &self.body {
+                matcher.check(&body, json.as_ref())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ValueMatcher {
+    fn from_config(config: &ValueConfig) -> Result<ValueMatcher> {
+        Ok(match config {
+            ValueConfig::Equals(Value::String(value)) => ValueMatcher::Equals(value.clone()),
+            ValueConfig::Equals(value @ (Value::Number(_) | Value::Bool(_))) => {
+                ValueMatcher::Equals(value.to_string())
+            }
+            ValueConfig::Equals(_) => {
+                bail!("Expected a value or one of equals, contains, regex or present")
+            }
+            ValueConfig::Matcher(ValueMatchConfig::Equals(value)) => {
+                ValueMatcher::Equals(value.clone())
+            }
+            ValueConfig::Matcher(ValueMatchConfig::Contains(value)) => {
+                ValueMatcher::Contains(value.clone())
+            }
+            ValueConfig::Matcher(ValueMatchConfig::Regex(regex)) => ValueMatcher::Regex(
+                Regex::new(regex).with_context(|| format!("Invalid regex '{}'", regex))?,
+            ),
+            ValueConfig::Matcher(ValueMatchConfig::Present(present)) => {
+                ValueMatcher::Present(*present)
+            }
+        })
+    }
+
+    /// Check all values given for a query parameter or header, one of them has to match
+    fn matches(&self, values: &[&str]) -> bool {
+        match self {
+            ValueMatcher::Equals(expected) => values.iter().any(|value| value == expected),
+            ValueMatcher::Contains(expected) => {
+                values.iter().any(|value| value.contains(expected.as_str()))
+            }
+            ValueMatcher::Regex(regex) => values.iter().any(|value| regex.is_match(value)),
+            ValueMatcher::Present(present) => values.is_empty() != *present,
+        }
+    }
+
+    /// Describe why a value didn't match
+    fn failure(&self) -> String {
+        match self {
+            ValueMatcher::Equals(expected) => format!("is not '{}'", expected),
+            ValueMatcher::Contains(expected) => format!("does not contain '{}'", expected),
+            ValueMatcher::Regex(regex) => format!("does not match '{}'", regex),
+            ValueMatcher::Present(true) => "is missing".to_string(),
+            ValueMatcher::Present(false) => "is present".to_string(),
+        }
+    }
+}
+
+impl BodyMatcher {
+    pub fn from_config(config: &BodyMatchConfig) -> Result<BodyMatcher> {
+        let given = [
+            config.contains.is_some(),
+            config.regex.is_some(),
+            config.json_path.is_some(),
+            config.json.is_some(),
+        ];
+        if given.iter().filter(|given| **given).count() != 1 {
+            bail!("Body matchers need exactly one of contains, regex, json_path or json");
+        }
+        if config.equals.is_some() && config.json_path.is_none() {
+            bail!("'equals' can only be used together with 'json_path'");
+        }
+
+        Ok(if let Some(contains) = &config.contains {
+            BodyMatcher::Contains(contains.clone())
+        } else if let Some(regex) = &config.regex {
+            BodyMatcher::Regex(
+                Regex::new(regex).with_context(|| format!("Invalid body regex '{}'", regex))?,
+            )
+        } else if let Some(path) = &config.json_path {
+            BodyMatcher::JsonPath {
+                path: JsonPath::parse(path)?,
+                equals: config.equals.clone(),
+            }
+        } else {
+            BodyMatcher::Json(config.json.clone().unwrap_or_default())
+        })
+    }
+
+    pub fn check(&self, body: &str, json: Option<&Value>) -> Result<(), String> {
+        match self {
+            BodyMatcher::Contains(expected) => {
+                if !body.contains(expected.as_str()) {
+                    return Err(format!("body does not contain '{}'", expected));
+                }
+            }
+            BodyMatcher::Regex(regex) => {
+                if !regex.is_match(body) {
+                    return Err(format!("body does not match '{}'", regex));
+                }
+            }
+            BodyMatcher::JsonPath { path, equals } => {
+                let json = json.ok_or_else(|| "body is not JSON".to_string())?;
+                let values = path.select(json);
+                match equals {
+                    None if values.is_empty() => {
+                        return Err(format!("body has nothing at '{}'", path));
+                    }
+                    Some(expected) if !values.contains(&expected) => {
+                        return Err(format!("body at '{}' is not {}", path, expected));
+                    }
+                    _ => {}
+                }
+            }
+            BodyMatcher::Json(expected) => {
+                let json = json.ok_or_else(|| "body is not JSON".to_string())?;
+                if !json_contains(json, expected) {
+                    return Err(format!("body does not contain {}", expected));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check whether `actual` contains `expected`
+///
+/// Objects may have additional fields and arrays additional elements, scalars have to be equal.
+fn json_contains(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => expected.iter().all(|(key, value)| {
+            actual
+                .get(key)
+                .is_some_and(|actual| json_contains(actual, value))
+        }),
+        (Value::Array(actual), Value::Array(expected)) => expected
+            .iter()
+            .all(|value| actual.iter().any(|actual| json_contains(actual, value))),
+        (actual, expected) => actual == expected,
+    }
+}
+
+/// A simple subset of JSONPath
+///
+/// Supported are the root `$`, child access via `.name` or `['name']`, array indices via `[0]`
+/// and wildcards via `.*` or `[*]`.
+#[derive(Debug)]
+pub struct JsonPath {
+    source: String,
+    steps: Vec<JsonPathStep>,
+}
+
+#[derive(Debug)]
+enum JsonPathStep {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<JsonPath> {
+        let invalid = || anyhow!("Invalid JSONPath '{}'", path);
+        let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+        let mut steps = vec![];
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+                let key = &after_dot[..end];
+                steps.push(match key {
+                    "" => return Err(invalid()),
+                    "*" => JsonPathStep::Wildcard,
+                    key => JsonPathStep::Key(key.to_string()),
+                });
+                rest = &after_dot[end..];
+            } else if let Some(after_bracket) = rest.strip_prefix('[') {
+                let end = after_bracket.find(']').ok_or_else(invalid)?;
+                let inner = &after_bracket[..end];
+                steps.push(if inner == "*" {
+                    JsonPathStep::Wildcard
+                } else if let Some(key) = inner
+                    .strip_prefix('\'')
+                    .and_then(|inner| inner.strip_suffix('\''))
+                    .or_else(|| {
+                        inner
+                            .strip_prefix('"')
+                            .and_then(|inner| inner.strip_suffix('"'))
+                    })
+                {
+                    JsonPathStep::Key(key.to_string())
+                } else {
+                    JsonPathStep::Index(inner.parse().map_err(|_| invalid())?)
+                });
+                rest = &after_bracket[end + 1..];
+            } else {
+                return Err(invalid());
+            }
+        }
+        Ok(JsonPath {
+            source: path.to_string(),
+            steps,
+        })
+    }
+
+    /// All values the path points to
+    pub fn select<'a>(&self, json: &'a Value) -> Vec<&'a Value> {
+        let mut current = vec![json];
+        for step in &self.steps {
+            current = current
+                .into_iter()
+                .flat_map(|value| -> Vec<&Value> {
+                    match (step, value) {
+                        (JsonPathStep::Key(key), Value::Object(map)) => {
+                            map.get(key).into_iter().collect()
+                        }
+                        (JsonPathStep::Index(i), Value::Array(array)) => {
+                            array.get(*i).into_iter().collect()
+                        }
+                        (JsonPathStep::Wildcard, Value::Object(map)) => map.values().collect(),
+                        (JsonPathStep::Wildcard, Value::Array(array)) => array.iter().collect(),
+                        _ => vec![],
+                    }
+                })
+                .collect();
+        }
+        current
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// A path pattern in the same syntax axum uses
+///
+/// `:name` matches exactly one segment and `*name` matches all remaining segments. Everything
+/// else has to match literally.
+#[derive(Debug)]
+pub struct PathPattern {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl PathPattern {
+    pub fn parse(pattern: &str) -> Result<PathPattern> {
+        if !pattern.starts_with('/') {
+            bail!("Path '{}' must start with '/'", pattern);
+        }
+
+        let parts = pattern[1..].split('/').collect::<Vec<_>>();
+        let mut segments = vec![];
+        for (i, part) in parts.iter().enumerate() {
+            let segment = if let Some(name) = part.strip_prefix(':') {
+                Segment::Param(name.to_string())
+            } else if let Some(name) = part.strip_prefix('*') {
+                if i != parts.len() - 1 {
+                    bail!("Wildcard '{}' must be the last segment of the path", part);
+                }
+                Segment::Wildcard(name.to_string())
+            } else {
+                Segment::Literal(part.to_string())
+            };
+            segments.push(segment);
+        }
+
+        Ok(PathPattern { segments })
+    }
+
+    /// Match a request path against this pattern, returning the captured segments on success
+    pub fn matches(&self, path: &str) -> Option<BTreeMap<String, String>> {
+        let parts = path.strip_prefix('/')?.split('/').collect::<Vec<_>>();
+        let mut params = BTreeMap::new();
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(literal) => {
+                    if parts.get(i) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => match parts.get(i) {
+                    Some(part) if !part.is_empty() => {
+                        params.insert(name.clone(), part.to_string());
+                    }
+                    _ => return None,
+                },
+                Segment::Wildcard(name) => {
+                    if i >= parts.len() {
+                        return None;
+                    }
+                    params.insert(name.clone(), parts[i..].join("/"));
+                    return Some(params);
+                }
+            }
+        }
+
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+
+        Some(params)
+    }
+}
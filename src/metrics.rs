@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+
+/// Prometheus metrics about the traffic dummyhttp received
+///
+/// These are exposed at `/__dummyhttp/metrics` and, if `--metrics-port` is given, at `/metrics`
+/// on that port.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_body_size: Histogram,
+    injected_delay: Histogram,
+    active_connections: IntGauge,
+}
+
+/// The delay that was injected into a response, kept around for the metrics
+#[derive(Debug, Clone, Copy)]
+pub struct InjectedDelay(pub Duration);
+
+impl Metrics {
+    pub fn new() -> Result<Metrics> {
+        let registry = Registry::new_custom(Some("dummyhttp".to_string()), None)?;
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Number of requests received"),
+            &["method", "route", "status"],
+        )?;
+        let request_body_size = Histogram::with_opts(
+            HistogramOpts::new("request_body_bytes", "Size of the request bodies received")
+                .buckets(exponential_buckets(64.0, 4.0, 8)?),
+        )?;
+        let injected_delay = Histogram::with_opts(HistogramOpts::new(
+            "injected_delay_seconds",
+            "Delay injected into responses by --delay",
+        ))?;
+        let active_connections =
+            IntGauge::new("active_connections", "Number of currently open connections")?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_body_size.clone()))?;
+        registry.register(Box::new(injected_delay.clone()))?;
+        registry.register(Box::new(active_connections.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            requests,
+            request_body_size,
+            injected_delay,
+            active_connections,
+        })
+    }
+
+    /// Count a request answered by `route`
+    ///
+    /// Requests are labeled by route name rather than by path and methods other than the
+    /// standard ones are counted as `other` so that the number of time series doesn't grow with
+    /// every distinct path or method requested.
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, body_size: usize) {
+        self.requests
+            .with_label_values(&[method_label(method), route, &status.to_string()])
+            .inc();
+        self.request_body_size.observe(body_size as f64);
+    }
+
+    pub fn observe_delay(&self, delay: Duration) {
+        self.injected_delay.observe(delay.as_secs_f64());
+    }
+
+    /// Count a connection as active until the returned guard is dropped
+    pub fn connection(&self) -> ConnectionGuard {
+        self.active_connections.inc();
+        ConnectionGuard(self.active_connections.clone())
+    }
+
+    /// Render all metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Failed to encode metrics");
+        String::from_utf8_lossy(&buffer).to_string()
+    }
+}
+
+/// Decrements the active connections when the connection is gone
+#[derive(Debug)]
+pub struct ConnectionGuard(IntGauge);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// The `method` label of a request, `other` for non-standard methods
+fn method_label(method: &str) -> &str {
+    const STANDARD: &[&str] = &[
+        "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+    ];
+    if STANDARD.contains(&method) {
+        method
+    } else {
+        "other"
+    }
+}
@@ -0,0 +1,673 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use axum::body::Bytes;
+use serde_json::{Map, Value};
+
+use crate::config::{PathConfig, PathMatchConfig, RouteConfig, ValueConfig, ValueMatchConfig};
+use crate::matching::IncomingRequest;
+
+const METHODS: &[&str] = &[
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Schemas nested deeper than this are not synthesized or validated to avoid endless recursion
+const MAX_DEPTH: usize = 8;
+
+/// A route generated from an operation of an OpenAPI document
+#[derive(Debug)]
+pub struct OpenApiRoute {
+    pub config: RouteConfig,
+
+    /// The body to send as is
+    pub body: Bytes,
+
+    /// Validates requests against the operation if `--openapi-validate` is given
+    pub validator: Option<RequestValidator>,
+}
+
+/// An OpenAPI 3 document given by `--openapi`
+#[derive(Debug)]
+pub struct OpenApi {
+    spec: Arc<Value>,
+}
+
+impl OpenApi {
+    /// Load an OpenAPI document in YAML or JSON format
+    pub fn from_file(path: &Path) -> Result<OpenApi> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read OpenAPI document '{}'", path.display()))?;
+        let is_json = path.extension().and_then(|ext| ext.to_str()) == Some("json");
+        let spec: Value = if is_json {
+            serde_json::from_str(&content).map_err(anyhow::Error::from)
+        } else {
+            serde_yaml::from_str(&content).map_err(anyhow::Error::from)
+        }
+        .with_context(|| format!("Failed to parse OpenAPI document '{}'", path.display()))?;
+
+        match spec.get("openapi").and_then(Value::as_str) {
+            Some(version) if version.starts_with("3.") => {}
+            _ => bail!(
+                "'{}' is not an OpenAPI 3 document (missing 'openapi: 3.x')",
+                path.display()
+            ),
+        }
+
+        Ok(OpenApi {
+            spec: Arc::new(spec),
+        })
+    }
+
+    /// Generate routes for all operations
+    ///
+    /// Every operation answers with its lowest documented 2xx response by default. The other
+    /// documented responses can be selected by sending `Prefer: code=<status>`. Paths are
+    /// prefixed with the path of the first server given for them, e.g. `/v1` for
+    /// `https://api.example.com/v1`.
+    pub fn routes(&self, validate: bool) -> Result<Vec<OpenApiRoute>> {
+        let paths = match self.spec.get("paths").and_then(Value::as_object) {
+            Some(paths) => paths,
+            None => return Ok(vec![]),
+        };
+
+        // Paths without placeholders go first so that e.g. `/users/me` wins over `/users/{id}`.
+        let mut paths = paths.iter().collect::<Vec<_>>();
+        paths.sort_by_key(|(path, _)| path.matches('{').count());
+
+        let base_path = server_path(&self.spec).unwrap_or_default();
+        let mut routes = vec![];
+        for (path, item) in paths {
+            let base_path = server_path(item).unwrap_or_else(|| base_path.clone());
+            let path_config = path_config(&format!("{}{}", base_path, path))
+                .with_context(|| format!("Invalid path '{}'", path))?;
+            for method in METHODS {
+                let operation = match item.get(*method) {
+                    Some(operation) => operation,
+                    None => continue,
+                };
+                let validator = if validate {
+                    Some(
+                        RequestValidator::new(&self.spec, item, operation).with_context(|| {
+                            format!("Invalid operation {} {}", method.to_uppercase(), path)
+                        })?,
+                    )
+                } else {
+                    None
+                };
+                routes.extend(self.operation_routes(&path_config, method, operation, validator));
+            }
+        }
+        Ok(routes)
+    }
+
+    fn operation_routes(
+        &self,
+        path: &PathConfig,
+        method: &str,
+        operation: &Value,
+        validator: Option<RequestValidator>,
+    ) -> Vec<OpenApiRoute> {
+        let empty = Map::new();
+        let responses = operation
+            .get("responses")
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+
+        let mut documented = responses
+            .iter()
+            .filter_map(|(status, response)| Some((parse_status(status)?, self.resolve(response))))
+            .collect::<Vec<_>>();
+        documented.sort_by_key(|(status, _)| *status);
+        let default = documented
+            .iter()
+            .find(|(status, _)| (200..300).contains(status))
+            .or_else(|| documented.first())
+            .map(|(status, response)| (*status, *response))
+            .or_else(|| {
+                responses
+                    .get("default")
+                    .map(|response| (200, self.resolve(response)))
+            });
+
+        let mut routes = vec![];
+        for (status, response) in &documented {
+            let mut route = self.route(path, method, *status, response, validator.clone());
+            let prefer = ValueConfig::Matcher(ValueMatchConfig::Regex(format!(
+                r"(^|[;,\s])code={}\b",
+                status
+            )));
+            route
+                .config
+                .request
+                .headers
+                .insert("prefer".to_string(), prefer);
+            routes.push(route);
+        }
+        if let Some((status, response)) = default {
+            routes.push(self.route(path, method, status, response, validator));
+        }
+        routes
+    }
+
+    fn route(
+        &self,
+        path: &PathConfig,
+        method: &str,
+        status: u16,
+        response: &Value,
+        validator: Option<RequestValidator>,
+    ) -> OpenApiRoute {
+        let mut config = RouteConfig::new("/").method(method).code(status);
+        config.path = path.clone();
+
+        let mut body = Bytes::new();
+        if let Some((content_type, media)) = pick_media_type(response) {
+            config = config.header("content-type", content_type.as_str());
+            if let Some(example) = self.example(media) {
+                body = match (&example, content_type.contains("json")) {
+                    (Value::String(text), false) => Bytes::from(text.clone()),
+                    _ => Bytes::from(serde_json::to_string_pretty(&example).unwrap_or_default()),
+                };
+            }
+        }
+
+        OpenApiRoute {
+            config,
+            body,
+            validator,
+        }
+    }
+
+    /// The example for a media type, synthesized from the schema if none is given
+    fn example(&self, media: &Value) -> Option<Value> {
+        if let Some(example) = media.get("example") {
+            return Some(example.clone());
+        }
+        if let Some(examples) = media.get("examples").and_then(Value::as_object) {
+            if let Some(example) = examples
+                .values()
+                .find_map(|example| self.resolve(example).get("value"))
+            {
+                return Some(example.clone());
+            }
+        }
+        media
+            .get("schema")
+            .map(|schema| synthesize(schema, &self.spec, 0))
+    }
+
+    fn resolve<'a>(&'a self, value: &'a Value) -> &'a Value {
+        resolve(value, &self.spec)
+    }
+}
+
+/// The path of the first server of a document or path item, without trailing slash
+fn server_path(value: &Value) -> Option<String> {
+    let server = value.get("servers")?.as_array()?.first()?;
+    let mut url = server.get("url")?.as_str()?.to_string();
+
+    // Fill in server variables with their defaults.
+    if let Some(variables) = server.get("variables").and_then(Value::as_object) {
+        for (name, variable) in variables {
+            if let Some(default) = variable.get("default").and_then(Value::as_str) {
+                url = url.replace(&format!("{{{}}}", name), default);
+            }
+        }
+    }
+
+    // Server URLs may be absolute or relative to the document.
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+        None => url.as_str(),
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        Some(String::new())
+    } else {
+        Some(format!("/{}", path))
+    }
+}
+
+/// Translate an OpenAPI path template into a path pattern
+///
+/// Placeholders spanning a whole segment become `:name`, others need a regex.
+fn path_config(path: &str) -> Result<PathConfig> {
+    if path.contains("{}") {
+        bail!("Placeholders need a name");
+    }
+    let whole_segments = path.split('/').all(|segment| {
+        !segment.contains('{') || (segment.starts_with('{') && segment.ends_with('}'))
+    });
+    if whole_segments {
+        let pattern = path
+            .split('/')
+            .map(|segment| match segment.strip_prefix('{') {
+                Some(name) => format!(":{}", name.trim_end_matches('}')),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        return Ok(PathConfig::Pattern(pattern));
+    }
+
+    let mut regex = String::from("^");
+    let mut rest = path;
+    while let Some(start) = rest.find('{') {
+        regex.push_str(&regex::escape(&rest[..start]));
+        let end = rest[start..]
+            .find('}')
+            .map_or(rest.len(), |end| start + end);
+        regex.push_str(&format!("(?P<{}>[^/]+)", group_name(&rest[start + 1..end])));
+        rest = rest.get(end + 1..).unwrap_or("");
+    }
+    regex.push_str(&regex::escape(rest));
+    regex.push('$');
+    Ok(PathConfig::Matcher(PathMatchConfig::Regex(regex)))
+}
+
+/// The regex group capturing a path parameter, as group names are limited to `[_0-9a-zA-Z]`
+fn group_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// Parse a response key such as `200` or `2XX`
+fn parse_status(status: &str) -> Option<u16> {
+    let status = status.to_uppercase().replace("XX", "00");
+    status
+        .parse()
+        .ok()
+        .filter(|status| (100..600).contains(status))
+}
+
+/// Pick the media type to answer with, preferring JSON
+fn pick_media_type(response: &Value) -> Option<(String, &Value)> {
+    let content = response.get("content")?.as_object()?;
+    content
+        .iter()
+        .find(|(content_type, _)| content_type.contains("json"))
+        .or_else(|| content.iter().next())
+        .map(|(content_type, media)| (content_type.clone(), media))
+}
+
+/// Follow a local `$ref` (e.g. `#/components/schemas/User`)
+fn resolve<'a>(value: &'a Value, spec: &'a Value) -> &'a Value {
+    let mut value = value;
+    // Limit the number of hops in case of circular references.
+    for _ in 0..MAX_DEPTH {
+        match value
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix('#'))
+            .and_then(|pointer| spec.pointer(pointer))
+        {
+            Some(target) => value = target,
+            None => break,
+        }
+    }
+    value
+}
+
+/// Make up a value matching a schema
+fn synthesize(schema: &Value, spec: &Value, depth: usize) -> Value {
+    let schema = resolve(schema, spec);
+    if let Some(example) = schema.get("example").or_else(|| schema.get("default")) {
+        return example.clone();
+    }
+    if let Some(first) = schema
+        .get("enum")
+        .and_then(Value::as_array)
+        .and_then(|values| values.first())
+    {
+        return first.clone();
+    }
+    if depth >= MAX_DEPTH {
+        return Value::Null;
+    }
+    if let Some(all_of) = schema.get("allOf").and_then(Value::as_array) {
+        let mut merged = Map::new();
+        for schema in all_of {
+            if let Value::Object(object) = synthesize(schema, spec, depth + 1) {
+                merged.extend(object);
+            }
+        }
+        return Value::Object(merged);
+    }
+    if let Some(first) = ["oneOf", "anyOf"]
+        .iter()
+        .find_map(|key| schema.get(*key)?.as_array()?.first())
+    {
+        return synthesize(first, spec, depth + 1);
+    }
+
+    match schema_type(schema) {
+        Some("object") => Value::Object(
+            schema
+                .get("properties")
+                .and_then(Value::as_object)
+                .map(|properties| {
+                    properties
+                        .iter()
+                        .map(|(key, schema)| (key.clone(), synthesize(schema, spec, depth + 1)))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        ),
+        Some("array") => Value::Array(
+            schema
+                .get("items")
+                .map(|items| vec![synthesize(items, spec, depth + 1)])
+                .unwrap_or_default(),
+        ),
+        Some("string") => Value::from(match schema.get("format").and_then(Value::as_str) {
+            Some("date-time") => "1970-01-01T00:00:00Z",
+            Some("date") => "1970-01-01",
+            Some("uuid") => "00000000-0000-0000-0000-000000000000",
+            Some("email") => "user@example.com",
+            Some("uri") | Some("url") => "https://example.com",
+            _ => "string",
+        }),
+        Some("integer") => schema
+            .get("minimum")
+            .and_then(Value::as_i64)
+            .map_or_else(|| Value::from(0), Value::from),
+        Some("number") => schema
+            .get("minimum")
+            .cloned()
+            .unwrap_or_else(|| Value::from(0.0)),
+        Some("boolean") => Value::Bool(true),
+        _ => Value::Null,
+    }
+}
+
+/// The type of a schema, guessing `object` if it has properties
+fn schema_type(schema: &Value) -> Option<&str> {
+    match schema.get("type") {
+        Some(Value::String(schema_type)) => Some(schema_type),
+        // OpenAPI 3.1 allows a list of types, use the first one that isn't null.
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|schema_type| *schema_type != "null"),
+        _ if schema.get("properties").is_some() => Some("object"),
+        _ => None,
+    }
+}
+
+/// Validates requests against the parameters and request body of an operation
+#[derive(Debug, Clone)]
+pub struct RequestValidator {
+    spec: Arc<Value>,
+    params: Vec<Parameter>,
+    body: Option<RequestBody>,
+}
+
+#[derive(Debug, Clone)]
+struct Parameter {
+    name: String,
+    location: String,
+    required: bool,
+    schema: Option<Value>,
+}
+
+#[derive(Debug, Clone)]
+struct RequestBody {
+    required: bool,
+
+    /// Schema of the JSON body, if any
+    schema: Option<Value>,
+}
+
+impl RequestValidator {
+    fn new(spec: &Arc<Value>, item: &Value, operation: &Value) -> Result<RequestValidator> {
+        // Parameters of the operation override the ones of the path with the same name and
+        // location.
+        let mut params = BTreeMap::new();
+        for param in [item, operation]
+            .iter()
+            .filter_map(|value| value.get("parameters")?.as_array())
+            .flatten()
+        {
+            let param = resolve(param, spec);
+            let name = param
+                .get("name")
+                .and_then(Value::as_str)
+                .context("Parameter without name")?;
+            let location = param
+                .get("in")
+                .and_then(Value::as_str)
+                .context("Parameter without location")?;
+            params.insert(
+                (location.to_string(), name.to_lowercase()),
+                Parameter {
+                    name: name.to_string(),
+                    location: location.to_string(),
+                    required: param.get("required").and_then(Value::as_bool) == Some(true),
+                    schema: param.get("schema").cloned(),
+                },
+            );
+        }
+
+        let body = operation.get("requestBody").map(|body| {
+            let body = resolve(body, spec);
+            RequestBody {
+                required: body.get("required").and_then(Value::as_bool) == Some(true),
+                schema: pick_media_type(body)
+                    .filter(|(content_type, _)| content_type.contains("json"))
+                    .and_then(|(_, media)| media.get("schema").cloned()),
+            }
+        });
+
+        Ok(RequestValidator {
+            spec: spec.clone(),
+            params: params.into_values().collect(),
+            body,
+        })
+    }
+
+    /// Check a request, returning a description of the first problem otherwise
+    pub fn validate(
+        &self,
+        request: &IncomingRequest<'_>,
+        path_params: &BTreeMap<String, String>,
+    ) -> Result<(), String> {
+        let query: Vec<(String, String)> =
+            serde_urlencoded::from_str(request.uri.query().unwrap_or("")).unwrap_or_default();
+
+        for param in &self.params {
+            let value = match param.location.as_str() {
+                "query" => query
+                    .iter()
+                    .find(|(key, _)| *key == param.name)
+                    .map(|(_, value)| value.clone()),
+                "header" => request
+                    .headers
+                    .get(param.name.as_str())
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| value.to_string()),
+                // Parameters only captured by a regex are named like its groups.
+                "path" => path_params
+                    .get(&param.name)
+                    .or_else(|| path_params.get(&group_name(&param.name)))
+                    .cloned(),
+                _ => continue,
+            };
+            let what = format!("{} parameter '{}'", param.location, param.name);
+            match value {
+                None if param.required => return Err(format!("{} is missing", what)),
+                None => {}
+                Some(value) => {
+                    if let Some(schema) = &param.schema {
+                        let value = coerce(&value, resolve(schema, &self.spec));
+                        validate_value(&value, schema, &self.spec, &what, 0)?;
+                    }
+                }
+            }
+        }
+
+        if let Some(body) = &self.body {
+            if request.body.is_empty() {
+                if body.required {
+                    return Err("request body is missing".to_string());
+                }
+            } else if let Some(schema) = &body.schema {
+                let json = serde_json::from_slice::<Value>(request.body)
+                    .map_err(|e| format!("request body is not valid JSON: {}", e))?;
+                validate_value(&json, schema, &self.spec, "request body", 0)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Turn a parameter given as string into the type its schema asks for, if possible
+fn coerce(value: &str, schema: &Value) -> Value {
+    let coerced = match schema_type(schema) {
+        Some("integer") => value.parse::<i64>().ok().map(Value::from),
+        Some("number") => value.parse::<f64>().ok().map(Value::from),
+        Some("boolean") => value.parse::<bool>().ok().map(Value::from),
+        _ => None,
+    };
+    coerced.unwrap_or_else(|| Value::from(value))
+}
+
+/// Validate a value against the commonly used subset of JSON schema
+///
+/// Schemas nested deeper than `MAX_DEPTH` accept any value.
+fn validate_value(
+    value: &Value,
+    schema: &Value,
+    spec: &Value,
+    what: &str,
+    depth: usize,
+) -> Result<(), String> {
+    if depth >= MAX_DEPTH {
+        return Ok(());
+    }
+    let schema = resolve(schema, spec);
+
+    if value.is_null() && schema.get("nullable").and_then(Value::as_bool) == Some(true) {
+        return Ok(());
+    }
+    if let Some(all_of) = schema.get("allOf").and_then(Value::as_array) {
+        for schema in all_of {
+            validate_value(value, schema, spec, what, depth + 1)?;
+        }
+    }
+    for key in ["oneOf", "anyOf"] {
+        if let Some(schemas) = schema.get(key).and_then(Value::as_array) {
+            if !schemas
+                .iter()
+                .any(|schema| validate_value(value, schema, spec, what, depth + 1).is_ok())
+            {
+                return Err(format!(
+                    "{} does not match any of the allowed schemas",
+                    what
+                ));
+            }
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!(
+                "{} must be one of {}",
+                what,
+                Value::from(allowed.clone())
+            ));
+        }
+    }
+
+    let expected = match schema_type(schema) {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let matches_type = match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    };
+    if !matches_type {
+        return Err(format!("{} must be of type {}", what, expected));
+    }
+
+    match value {
+        Value::Object(object) => {
+            for required in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !object.contains_key(required) {
+                    return Err(format!("{} is missing property '{}'", what, required));
+                }
+            }
+            if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+                for (key, value) in object {
+                    if let Some(schema) = properties.get(key) {
+                        validate_value(
+                            value,
+                            schema,
+                            spec,
+                            &format!("{}.{}", what, key),
+                            depth + 1,
+                        )?;
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_value(item, schema, spec, &format!("{}[{}]", what, i), depth + 1)?;
+                }
+            }
+        }
+        Value::String(string) => {
+            let length = string.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if length < min {
+                    return Err(format!("{} must be at least {} characters long", what, min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if length > max {
+                    return Err(format!("{} must be at most {} characters long", what, max));
+                }
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < min {
+                    return Err(format!("{} must be at least {}", what, min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > max {
+                    return Err(format!("{} must be at most {}", what, max));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
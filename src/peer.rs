@@ -0,0 +1,56 @@
+use std::fmt;
+use std::net::SocketAddr;
+
+use axum::extract::connect_info::Connected;
+use hyper::server::conn::AddrStream;
+
+/// The other end of a connection, as logged and exposed to body templates as `request.peer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peer {
+    Tcp(SocketAddr),
+
+    /// A client connected via --unix-socket, with its credentials if the OS told us
+    Unix(Option<UnixCredentials>),
+}
+
+/// Credentials of the process on the other end of a Unix socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnixCredentials {
+    /// Not available on every platform
+    pub pid: Option<i32>,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Peer::Tcp(addr) => write!(f, "{addr}"),
+            Peer::Unix(Some(credentials)) => {
+                write!(f, "unix:")?;
+                if let Some(pid) = credentials.pid {
+                    write!(f, "pid={pid},")?;
+                }
+                write!(f, "uid={},gid={}", credentials.uid, credentials.gid)
+            }
+            Peer::Unix(None) => write!(f, "unix"),
+        }
+    }
+}
+
+impl Connected<&AddrStream> for Peer {
+    fn connect_info(target: &AddrStream) -> Peer {
+        Peer::Tcp(target.remote_addr())
+    }
+}
+
+#[cfg(unix)]
+impl Connected<&tokio::net::UnixStream> for Peer {
+    fn connect_info(target: &tokio::net::UnixStream) -> Peer {
+        Peer::Unix(target.peer_cred().ok().map(|cred| UnixCredentials {
+            pid: cred.pid(),
+            uid: cred.uid(),
+            gid: cred.gid(),
+        }))
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use axum::body::Bytes;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use hyper::header::{HeaderName, CONNECTION, CONTENT_LENGTH, HOST, TRANSFER_ENCODING, UPGRADE};
+
+use crate::recording::{RecordedRequest, RecordedResponse, Recorder, Recording};
+
+/// Forwards requests to the upstream given by `--proxy-to`, optionally recording them
+#[derive(Debug)]
+pub struct Proxy {
+    upstream: String,
+    client: reqwest::Client,
+    recorder: Option<Recorder>,
+    quiet: bool,
+}
+
+impl Proxy {
+    pub fn new(upstream: &str, recorder: Option<Recorder>, quiet: bool) -> Result<Proxy> {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .context("Failed to create HTTP client for proxying")?;
+        Ok(Proxy {
+            upstream: upstream.trim_end_matches('/').to_string(),
+            client,
+            recorder,
+            quiet,
+        })
+    }
+
+    /// Forward a request upstream and return the upstream's response
+    ///
+    /// Failing to record the request is reported but doesn't keep the response from the client.
+    pub async fn forward(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        body: Bytes,
+    ) -> Result<(StatusCode, HeaderMap, Bytes)> {
+        let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+        let url = format!("{}{}", self.upstream, path_and_query);
+
+        let mut upstream_headers = headers.clone();
+        strip_hop_by_hop_headers(&mut upstream_headers);
+        upstream_headers.remove(HOST);
+
+        let upstream_resp = self
+            .client
+            .request(method.clone(), &url)
+            .headers(upstream_headers)
+            .body(body.clone())
+            .send()
+            .await
+            .with_context(|| format!("Failed to forward request to '{}'", url))?;
+
+        let status = upstream_resp.status();
+        let mut resp_headers = upstream_resp.headers().clone();
+        let resp_body = upstream_resp
+            .bytes()
+            .await
+            .with_context(|| format!("Failed to read response from '{}'", url))?;
+        strip_hop_by_hop_headers(&mut resp_headers);
+        resp_headers.remove(CONTENT_LENGTH);
+
+        if let Some(recorder) = &self.recorder {
+            let recorded = recorder.record(&Recording {
+                request: RecordedRequest::new(method, uri, headers, &body),
+                response: RecordedResponse::new(status, &resp_headers, &resp_body),
+            });
+            if let Err(e) = recorded {
+                if !self.quiet {
+                    eprintln!("Failed to record request to {}: {e:#}", url);
+                }
+            }
+        }
+
+        Ok((status, resp_headers, resp_body))
+    }
+}
+
+/// Remove headers which only apply to a single connection and must not be forwarded
+fn strip_hop_by_hop_headers(headers: &mut HeaderMap) {
+    for name in [CONNECTION, TRANSFER_ENCODING, UPGRADE] {
+        headers.remove(name);
+    }
+    for name in ["keep-alive", "proxy-connection"] {
+        headers.remove(HeaderName::from_static(name));
+    }
+}
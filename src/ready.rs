@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ArgEnum;
+use serde::Serialize;
+
+use crate::listen::{Listen, ListenAddr};
+
+/// How the address is printed via --print-address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum AddressFormat {
+    /// One URL per line
+    Plain,
+
+    /// A single JSON object with the URL, port and pid
+    Json,
+}
+
+/// Where a started dummyhttp can be reached, as written to --ready-file
+#[derive(Debug, Serialize)]
+pub struct Ready {
+    /// URL of the first listener
+    pub url: String,
+
+    /// Port of the first listener, `null` for Unix sockets
+    pub port: Option<u16>,
+
+    pub pid: u32,
+
+    /// URLs of all listeners
+    pub urls: Vec<String>,
+}
+
+impl Ready {
+    /// Describe the bound `listeners`
+    pub fn new<'a>(listeners: impl IntoIterator<Item = &'a Listen>) -> Ready {
+        let listeners = listeners.into_iter().collect::<Vec<_>>();
+        let urls = listeners
+            .iter()
+            .map(|listen| listen.url())
+            .collect::<Vec<_>>();
+        let port = listeners.first().and_then(|listen| match listen.addr {
+            ListenAddr::Tcp(addr) => Some(addr.port()),
+            _ => None,
+        });
+        Ready {
+            url: urls.first().cloned().unwrap_or_default(),
+            port,
+            pid: std::process::id(),
+            urls,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize ready info")
+    }
+
+    pub fn format(&self, format: AddressFormat) -> String {
+        match format {
+            AddressFormat::Plain => self.urls.join("\n"),
+            AddressFormat::Json => self.to_json(),
+        }
+    }
+
+    /// Write the JSON to `path`
+    ///
+    /// The file is written next to `path` first and then renamed so that whoever is waiting for
+    /// it never reads it half written.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, self.to_json() + "\n")
+            .and_then(|_| fs::rename(&partial, path))
+            .with_context(|| format!("Failed to write ready file '{}'", path.display()))
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use axum::body::Bytes;
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use hyper::header::{
+    HeaderName, AUTHORIZATION, CONTENT_LENGTH, COOKIE, PROXY_AUTHORIZATION, TRANSFER_ENCODING,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// A request/response pair as recorded by `--record` and served by `--replay`
+///
+/// Recordings are stored as one JSON object per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_headers")]
+    pub headers: Vec<(String, String)>,
+    #[serde(flatten)]
+    pub body: RecordedBody,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, deserialize_with = "deserialize_headers")]
+    pub headers: Vec<(String, String)>,
+    #[serde(flatten)]
+    pub body: RecordedBody,
+}
+
+/// Headers as list of name/value pairs so that repeated headers such as `set-cookie` survive
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(key, value)| {
+            let value = value.to_str().unwrap_or("<unprintable>");
+            (key.as_str().to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Request headers carrying credentials, which are redacted in recordings
+const REDACTED_HEADERS: &[HeaderName] = &[AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION];
+
+/// Read headers as list of pairs or, as written by earlier versions, as map
+fn deserialize_headers<'de, D>(deserializer: D) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Headers {
+        Pairs(Vec<(String, String)>),
+        Map(BTreeMap<String, String>),
+    }
+
+    Ok(match Headers::deserialize(deserializer)? {
+        Headers::Pairs(pairs) => pairs,
+        Headers::Map(map) => map.into_iter().collect(),
+    })
+}
+
+/// A body which is kept as text if possible and base64 encoded otherwise
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedBody {
+    #[serde(default)]
+    pub body: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub base64: bool,
+}
+
+impl RecordedBody {
+    pub fn new(bytes: &[u8]) -> RecordedBody {
+        match std::str::from_utf8(bytes) {
+            Ok(body) => RecordedBody {
+                body: body.to_string(),
+                base64: false,
+            },
+            Err(_) => RecordedBody {
+                body: base64::encode(bytes),
+                base64: true,
+            },
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Bytes> {
+        if self.base64 {
+            Ok(base64::decode(&self.body)
+                .context("Invalid base64 body")?
+                .into())
+        } else {
+            Ok(Bytes::from(self.body.clone()))
+        }
+    }
+}
+
+impl RecordedRequest {
+    /// Credentials in headers are replaced by `<redacted>` as recordings are often committed
+    pub fn new(method: &Method, uri: &Uri, headers: &HeaderMap, body: &[u8]) -> RecordedRequest {
+        let mut headers = header_pairs(headers);
+        for (key, value) in &mut headers {
+            if REDACTED_HEADERS.iter().any(|name| name == key.as_str()) {
+                *value = "<redacted>".to_string();
+            }
+        }
+        RecordedRequest {
+            method: method.to_string(),
+            path: uri.path().to_string(),
+            query: uri.query().map(|query| query.to_string()),
+            headers,
+            body: RecordedBody::new(body),
+        }
+    }
+}
+
+impl RecordedResponse {
+    pub fn new(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> RecordedResponse {
+        RecordedResponse {
+            status: status.as_u16(),
+            headers: header_pairs(headers),
+            body: RecordedBody::new(body),
+        }
+    }
+
+    /// Status, headers and body to send when replaying this response
+    ///
+    /// Framing headers are dropped as they are set again for the body we actually send.
+    pub fn to_parts(&self) -> Result<(StatusCode, HeaderMap, Bytes)> {
+        let status = StatusCode::from_u16(self.status)
+            .with_context(|| format!("Invalid status code {}", self.status))?;
+        let mut headers = HeaderMap::new();
+        for (key, value) in &self.headers {
+            let hn = HeaderName::from_bytes(key.as_bytes())
+                .with_context(|| format!("Invalid header name '{}'", key))?;
+            if hn == CONTENT_LENGTH || hn == TRANSFER_ENCODING {
+                continue;
+            }
+            let hv = HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value for header '{}'", key))?;
+            headers.append(hn, hv);
+        }
+        Ok((status, headers, self.body.to_bytes()?))
+    }
+}
+
+/// Appends recordings to the file given by `--record`
+#[derive(Debug)]
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    pub fn new(path: &Path) -> Result<Recorder> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open recording file '{}'", path.display()))?;
+        Ok(Recorder {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, recording: &Recording) -> Result<()> {
+        let mut line = serde_json::to_string(recording)?;
+        line.push('\n');
+        self.file
+            .lock()
+            .unwrap()
+            .write_all(line.as_bytes())
+            .context("Failed to write recording")?;
+        Ok(())
+    }
+}
+
+/// Serves the responses from a recording file given by `--replay`
+///
+/// Requests are matched by method, path, query and body. If the same request was recorded
+/// multiple times, the recorded responses are served in order and the last one is repeated
+/// once all of them have been served.
+#[derive(Debug)]
+pub struct Replay {
+    entries: Vec<ReplayEntry>,
+}
+
+#[derive(Debug)]
+struct ReplayEntry {
+    request: RecordedRequest,
+    responses: Vec<RecordedResponse>,
+    served: AtomicUsize,
+}
+
+impl Replay {
+    pub fn from_file(path: &Path) -> Result<Replay> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open replay file '{}'", path.display()))?;
+
+        let mut entries = Vec::<ReplayEntry>::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let recording: Recording = serde_json::from_str(&line).with_context(|| {
+                format!(
+                    "Failed to parse line {} of replay file '{}'",
+                    i + 1,
+                    path.display()
+                )
+            })?;
+            match entries
+                .iter_mut()
+                .find(|entry| same_request(&entry.request, &recording.request))
+            {
+                Some(entry) => entry.responses.push(recording.response),
+                None => entries.push(ReplayEntry {
+                    request: recording.request,
+                    responses: vec![recording.response],
+                    served: AtomicUsize::new(0),
+                }),
+            }
+        }
+
+        Ok(Replay { entries })
+    }
+
+    /// Find the next recorded response for a request
+    pub fn find(&self, request: &RecordedRequest) -> Option<&RecordedResponse> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| same_request(&entry.request, request))?;
+        let served = entry.served.fetch_add(1, Ordering::SeqCst);
+        entry
+            .responses
+            .get(served)
+            .or_else(|| entry.responses.last())
+    }
+}
+
+fn same_request(a: &RecordedRequest, b: &RecordedRequest) -> bool {
+    a.method.eq_ignore_ascii_case(&b.method)
+        && a.path == b.path
+        && a.query.as_deref().unwrap_or("") == b.query.as_deref().unwrap_or("")
+        && a.body == b.body
+}
@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "tls")]
+use axum_server::tls_rustls::RustlsConfig;
+
+use crate::args::Args;
+use crate::logging;
+use crate::routes::RouteSet;
+#[cfg(feature = "tls")]
+use crate::self_signed::SelfSigned;
+#[cfg(feature = "tls")]
+use crate::tls;
+
+/// How often watched files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reloads the routes and TLS certificate from their files
+///
+/// The new routes are swapped in atomically once they were compiled successfully. If they
+/// can't be compiled, the error is logged and the previous routes stay active.
+#[derive(Debug, Clone)]
+pub struct Reloader {
+    args: Args,
+    route_sets: Vec<RouteSet>,
+    #[cfg(feature = "tls")]
+    tls_config: Option<RustlsConfig>,
+    #[cfg(feature = "tls")]
+    self_signed: Option<SelfSigned>,
+}
+
+impl Reloader {
+    pub fn new(args: &Args, route_sets: Vec<RouteSet>) -> Reloader {
+        Reloader {
+            args: args.clone(),
+            route_sets,
+            #[cfg(feature = "tls")]
+            tls_config: None,
+            #[cfg(feature = "tls")]
+            self_signed: None,
+        }
+    }
+
+    /// Also reload the certificate and client CA of this TLS config
+    ///
+    /// The certificate is read from the --tls-cert and --tls-key files or, if given, kept at
+    /// `self_signed`.
+    #[cfg(feature = "tls")]
+    pub fn with_tls_config(
+        mut self,
+        tls_config: RustlsConfig,
+        self_signed: Option<SelfSigned>,
+    ) -> Reloader {
+        self.tls_config = Some(tls_config);
+        self.self_signed = self_signed;
+        self
+    }
+
+    pub async fn reload(&self) {
+        for route_set in &self.route_sets {
+            match route_set.reload() {
+                Ok(()) => self.log("Reloaded configuration"),
+                Err(e) => self.log(&format!(
+                    "Failed to reload configuration, keeping the previous one: {e:#}"
+                )),
+            }
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(tls_config) = &self.tls_config {
+            match tls::server_config(&self.args, self.self_signed.as_ref()) {
+                Ok(server_config) => {
+                    tls_config.reload_from_config(server_config);
+                    self.log("Reloaded TLS certificate");
+                }
+                Err(e) => self.log(&format!(
+                    "Failed to reload TLS certificate, keeping the previous one: {e:#}"
+                )),
+            }
+        }
+    }
+
+    /// Reload whenever one of the files the routes or TLS certificate came from changes
+    pub async fn watch(self) {
+        let mut mtimes = self.mtimes();
+        loop {
+            tokio::time::sleep(WATCH_INTERVAL).await;
+            if self.mtimes() != mtimes {
+                self.reload().await;
+                // The set of files might have changed as well.
+                mtimes = self.mtimes();
+            }
+        }
+    }
+
+    #[cfg_attr(not(feature = "tls"), allow(unused_mut))]
+    fn watched_files(&self) -> Vec<PathBuf> {
+        let mut files = self
+            .route_sets
+            .iter()
+            .flat_map(|route_set| route_set.routes.current().files().to_vec())
+            .collect::<Vec<_>>();
+        #[cfg(feature = "tls")]
+        files.extend(
+            [
+                &self.args.tls_cert,
+                &self.args.tls_key,
+                &self.args.tls_client_ca,
+            ]
+            .iter()
+            .copied()
+            .flatten()
+            .cloned(),
+        );
+        files
+    }
+
+    /// Modification times of the watched files, `None` for files that are missing
+    fn mtimes(&self) -> Vec<Option<SystemTime>> {
+        self.watched_files()
+            .iter()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+
+    fn log(&self, message: &str) {
+        if !self.args.quiet {
+            println!("{}", logging::format_message(self.args.log_format, message));
+        }
+    }
+}
@@ -0,0 +1,537 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, bail, Context, Result};
+use axum::body::Bytes;
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use hyper::header::{HeaderName, CONTENT_TYPE};
+
+use crate::args::Args;
+use crate::chaos::{self, Fault};
+use crate::compression::Compression;
+use crate::config::{Config, ResponseConfig, RouteConfig};
+use crate::delay::Delay;
+use crate::files;
+use crate::matching::{IncomingRequest, PathMatcher, RequestMatcher};
+use crate::openapi::{OpenApi, RequestValidator};
+use crate::scenarios::Scenarios;
+use crate::sse::SseScript;
+use crate::streaming::Transfer;
+use crate::websocket::WebSocketScript;
+
+/// Everything dummyhttp needs to know to answer a request
+///
+/// This is compiled at startup from the `--config` file (if any) and the response flags given on
+/// the command line. The latter act as the catch-all default. It is compiled again whenever the
+/// files it was compiled from change, see `ActiveRoutes`.
+#[derive(Debug)]
+pub struct RouteTable {
+    routes: Vec<Route>,
+    default: ResponseTemplate,
+
+    /// The files this table was compiled from
+    files: Vec<PathBuf>,
+}
+
+/// The route table currently in use
+///
+/// Requests get hold of the current table once so that a reload in the middle of a request
+/// doesn't affect it.
+#[derive(Debug, Clone)]
+pub struct ActiveRoutes(Arc<RwLock<Arc<RouteTable>>>);
+
+impl ActiveRoutes {
+    pub fn new(routes: RouteTable) -> ActiveRoutes {
+        ActiveRoutes(Arc::new(RwLock::new(Arc::new(routes))))
+    }
+
+    pub fn current(&self) -> Arc<RouteTable> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, routes: RouteTable) {
+        *self.0.write().unwrap() = Arc::new(routes);
+    }
+}
+
+/// Routes compiled from one config file together with the scenarios they drive
+///
+/// Every listener serves one route set. Listeners without a config of their own share the set
+/// compiled from --config.
+#[derive(Debug, Clone)]
+pub struct RouteSet {
+    /// The args the routes are compiled from, with --config pointing at this set's file
+    pub args: Args,
+    pub extra_routes: Vec<RouteConfig>,
+    pub routes: ActiveRoutes,
+    pub scenarios: Scenarios,
+}
+
+impl RouteSet {
+    pub fn new(args: &Args, extra_routes: Vec<RouteConfig>) -> Result<RouteSet> {
+        let routes = RouteTable::from_args(args, extra_routes.clone())?;
+        let scenarios = Scenarios::new(routes.scenario_names());
+        Ok(RouteSet {
+            args: args.clone(),
+            extra_routes,
+            routes: ActiveRoutes::new(routes),
+            scenarios,
+        })
+    }
+
+    /// Compile the routes anew and swap them in if that worked
+    pub fn reload(&self) -> Result<()> {
+        let routes = RouteTable::from_args(&self.args, self.extra_routes.clone())?;
+        self.scenarios.add(routes.scenario_names());
+        self.routes.replace(routes);
+        Ok(())
+    }
+}
+
+/// A compiled route from the config file
+#[derive(Debug)]
+pub struct Route {
+    /// Describes the route in log messages
+    name: String,
+    methods: Option<Vec<Method>>,
+    path: PathMatcher,
+    request: RequestMatcher,
+    scenario: Option<ScenarioStep>,
+    response: ResponseTemplate,
+    validator: Option<RequestValidator>,
+}
+
+/// How a route takes part in a scenario
+#[derive(Debug)]
+struct ScenarioStep {
+    name: String,
+    required_state: Option<String>,
+    new_state: Option<String>,
+}
+
+/// A response whose body still needs to be rendered
+#[derive(Debug, Clone)]
+pub struct ResponseTemplate {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: ResponseBody,
+    pub delay: Option<Delay>,
+    pub transfer: Option<Transfer>,
+    pub compression: Compression,
+
+    /// Sent as Content-Encoding instead of the encoding actually used
+    pub fake_content_encoding: Option<HeaderValue>,
+    pub faults: Vec<Fault>,
+
+    /// Set for routes that upgrade to a WebSocket instead of answering with the above
+    pub websocket: Option<Arc<WebSocketScript>>,
+
+    /// Set for routes that stream Server-Sent Events instead of the above body
+    pub sse: Option<Arc<SseScript>>,
+}
+
+/// The body of a response
+#[derive(Debug, Clone)]
+pub enum ResponseBody {
+    /// Rendered as Tera template for every request
+    Template(String),
+
+    /// Sent as is
+    Static(Bytes),
+}
+
+/// The result of looking up a request in the `RouteTable`
+#[derive(Debug)]
+pub struct RouteMatch<'a> {
+    pub response: &'a ResponseTemplate,
+
+    /// Path segments captured by `:name` and `*name` placeholders
+    pub params: BTreeMap<String, String>,
+
+    /// Set for routes generated from an OpenAPI document with `--openapi-validate`
+    pub validator: Option<&'a RequestValidator>,
+
+    /// Name of the matched route, `None` for the default response
+    pub route: Option<&'a str>,
+
+    /// Scenario state change to commit once the response was built successfully
+    pub transition: Option<Transition>,
+}
+
+/// A scenario state change reserved by `RouteTable::find`
+///
+/// The scenario is moved into its new state while the route is matched so that concurrent
+/// requests can't match the same route. Unless `commit` is called, the scenario is moved back
+/// into its previous state once this is dropped.
+#[derive(Debug)]
+pub struct Transition {
+    scenarios: Scenarios,
+    scenario: String,
+    previous: String,
+    next: String,
+    committed: bool,
+}
+
+impl Transition {
+    /// Keep the scenario in its new state
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for Transition {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        let mut states = self.scenarios.lock();
+        if let Some(state) = states.get_mut(&self.scenario) {
+            // Don't undo what a later request did in the meantime.
+            if *state == self.next {
+                *state = self.previous.clone();
+            }
+        }
+    }
+}
+
+impl RouteTable {
+    /// Compile the routes for the given args, loading the config file if one was given
+    ///
+    /// `extra_routes` are matched after the routes from the config file, followed by the routes
+    /// generated from the `--openapi` document.
+    pub fn from_args(args: &Args, extra_routes: Vec<RouteConfig>) -> Result<RouteTable> {
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        config.routes.extend(extra_routes);
+
+        // Body files in the config are relative to the config file.
+        let base_dir = args
+            .config
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new(""));
+
+        let mut routes = config
+            .routes
+            .iter()
+            .enumerate()
+            .map(|(i, route)| {
+                Route::from_config(i, route, args, base_dir)
+                    .with_context(|| format!("Invalid route #{} ('{}')", i + 1, route.path))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(path) = &args.openapi {
+            for openapi_route in OpenApi::from_file(path)?.routes(args.openapi_validate)? {
+                let mut route =
+                    Route::from_config(routes.len(), &openapi_route.config, args, base_dir)
+                        .with_context(|| {
+                            format!("Invalid OpenAPI route '{}'", openapi_route.config.path)
+                        })?;
+                route.response.body = ResponseBody::Static(openapi_route.body);
+                route.validator = openapi_route.validator;
+                routes.push(route);
+            }
+        }
+
+        let default = match &config.default {
+            Some(default) => ResponseTemplate::from_config(default, args, base_dir)
+                .context("Invalid default response")?,
+            None => ResponseTemplate::from_args(args)?,
+        };
+
+        let body_files = config
+            .routes
+            .iter()
+            .map(|route| &route.response)
+            .chain(&config.default)
+            .filter_map(|response| response.body_file.as_ref())
+            .map(|path| base_dir.join(path));
+        let files = [&args.config, &args.openapi, &args.body_file]
+            .iter()
+            .copied()
+            .flatten()
+            .cloned()
+            .chain(body_files)
+            .collect();
+
+        Ok(RouteTable {
+            routes,
+            default,
+            files,
+        })
+    }
+
+    /// The files this table was compiled from
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Find the first route matching a request, returning why no route matched otherwise
+    ///
+    /// Routes belonging to a scenario only match while the scenario is in their required state.
+    /// If the matching route moves its scenario into a new state, that happens right away and
+    /// is undone unless the caller commits `RouteMatch::transition`, so that requests failing to
+    /// get a response don't count.
+    pub fn find(
+        &self,
+        request: &IncomingRequest<'_>,
+        scenarios: &Scenarios,
+    ) -> Result<RouteMatch<'_>, String> {
+        let mut states = scenarios.lock();
+        let mut reasons = vec![];
+        for route in &self.routes {
+            let params = match route.path.matches(request.uri.path()) {
+                Some(params) => params,
+                None => continue,
+            };
+            let mismatch = |reason: String| format!("{} {}", route.name, reason);
+            if let Some(methods) = &route.methods {
+                if !methods.contains(request.method) {
+                    reasons.push(mismatch(format!("doesn't accept {}", request.method)));
+                    continue;
+                }
+            }
+            if let Err(reason) = route.request.check(request) {
+                reasons.push(mismatch(reason));
+                continue;
+            }
+            let mut transition = None;
+            if let Some(step) = &route.scenario {
+                if let Some(required_state) = &step.required_state {
+                    let state = states.get(&step.name);
+                    if state != Some(required_state) {
+                        reasons.push(mismatch(format!(
+                            "needs scenario '{}' in state '{}' but it is in '{}'",
+                            step.name,
+                            required_state,
+                            state.map(String::as_str).unwrap_or_default()
+                        )));
+                        continue;
+                    }
+                }
+                if let (Some(new_state), Some(state)) =
+                    (&step.new_state, states.get_mut(&step.name))
+                {
+                    let previous = std::mem::replace(state, new_state.clone());
+                    transition = Some(Transition {
+                        scenarios: scenarios.clone(),
+                        scenario: step.name.clone(),
+                        previous,
+                        next: new_state.clone(),
+                        committed: false,
+                    });
+                }
+            }
+            return Ok(RouteMatch {
+                response: &route.response,
+                params,
+                validator: route.validator.as_ref(),
+                route: Some(&route.name),
+                transition,
+            });
+        }
+
+        if reasons.is_empty() {
+            Err(format!("no route for path {}", request.uri.path()))
+        } else {
+            Err(reasons.join("; "))
+        }
+    }
+
+    /// Whether any routes were configured
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Names of all scenarios used by the routes
+    pub fn scenario_names(&self) -> impl Iterator<Item = String> + '_ {
+        self.routes
+            .iter()
+            .filter_map(|route| route.scenario.as_ref().map(|step| step.name.clone()))
+    }
+
+    /// The response given on the command line for requests not matching any route
+    pub fn default_response(&self) -> &ResponseTemplate {
+        &self.default
+    }
+}
+
+impl Route {
+    fn from_config(
+        index: usize,
+        config: &RouteConfig,
+        args: &Args,
+        base_dir: &Path,
+    ) -> Result<Route> {
+        let methods = config
+            .method
+            .clone()
+            .map(|methods| {
+                methods
+                    .into_vec()
+                    .iter()
+                    .map(|method| {
+                        Method::from_bytes(method.to_uppercase().as_bytes())
+                            .map_err(|_| anyhow!("Invalid method '{}'", method))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?;
+
+        let scenario = match &config.scenario {
+            Some(name) => Some(ScenarioStep {
+                name: name.clone(),
+                required_state: config.required_state.clone(),
+                new_state: config.new_state.clone(),
+            }),
+            None if config.required_state.is_some() || config.new_state.is_some() => {
+                bail!("'required_state' and 'new_state' need a 'scenario'")
+            }
+            None => None,
+        };
+
+        Ok(Route {
+            name: format!("route #{} ({})", index + 1, config.path),
+            methods,
+            path: PathMatcher::from_config(&config.path)?,
+            request: RequestMatcher::from_config(&config.request)?,
+            scenario,
+            response: ResponseTemplate::from_config(&config.response, args, base_dir)?,
+            validator: None,
+        })
+    }
+}
+
+impl ResponseTemplate {
+    /// The response given via `--code`, `--headers` and `--body`
+    pub fn from_args(args: &Args) -> Result<ResponseTemplate> {
+        let status = StatusCode::from_u16(args.code)
+            .with_context(|| format!("Invalid status code {}", args.code))?;
+
+        let mut headers = HeaderMap::new();
+        for header in &args.headers {
+            let val = header.iter().next().unwrap();
+            headers.insert(val.0.clone(), val.1.clone());
+        }
+
+        let body = match &args.body_file {
+            Some(path) => load_body_file(path, args.render_body_file, &mut headers)?,
+            None => ResponseBody::Template(args.body.clone()),
+        };
+        chaos::check_probabilities(&args.faults)?;
+
+        Ok(ResponseTemplate {
+            status,
+            headers,
+            body,
+            delay: args.delay.clone(),
+            transfer: Transfer::from_settings(
+                args.trickle.as_ref(),
+                args.chunk_size,
+                args.chunk_delay.as_ref(),
+                args.bandwidth,
+            )?,
+            compression: args.compression,
+            fake_content_encoding: args.fake_content_encoding.clone(),
+            faults: args.faults.clone(),
+            websocket: None,
+            sse: None,
+        })
+    }
+
+    fn from_config(
+        config: &ResponseConfig,
+        args: &Args,
+        base_dir: &Path,
+    ) -> Result<ResponseTemplate> {
+        let status = StatusCode::from_u16(config.code)
+            .with_context(|| format!("Invalid status code {}", config.code))?;
+
+        let mut headers = HeaderMap::new();
+        for (key, value) in &config.headers {
+            let hn = HeaderName::from_lowercase(key.to_lowercase().as_bytes())
+                .with_context(|| format!("Invalid header name '{}'", key))?;
+            let hv = HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value for header '{}'", key))?;
+            headers.insert(hn, hv);
+        }
+
+        let body = match &config.body_file {
+            Some(_) if !config.body.is_empty() => {
+                bail!("Only one of body and body_file can be given")
+            }
+            Some(path) => load_body_file(&base_dir.join(path), config.template, &mut headers)?,
+            None => ResponseBody::Template(config.body.clone()),
+        };
+
+        if config.websocket.is_some() && config.sse.is_some() {
+            bail!("Only one of websocket and sse can be given");
+        }
+        let has_body_settings = !config.body.is_empty()
+            || config.body_file.is_some()
+            || config.trickle.is_some()
+            || config.chunk_size.is_some()
+            || config.chunk_delay.is_some()
+            || config.bandwidth.is_some();
+        if config.sse.is_some() && has_body_settings {
+            bail!("sse can't be combined with body, body_file, trickle, chunk_size, chunk_delay or bandwidth");
+        }
+        let faults = config.faults.clone().unwrap_or_else(|| args.faults.clone());
+        chaos::check_probabilities(&faults)?;
+
+        Ok(ResponseTemplate {
+            status,
+            headers,
+            body,
+            delay: config.delay.clone(),
+            transfer: Transfer::from_settings(
+                config.trickle.as_ref(),
+                config.chunk_size,
+                config.chunk_delay.as_ref(),
+                config.bandwidth,
+            )?,
+            compression: config.compression.unwrap_or(args.compression),
+            fake_content_encoding: config
+                .fake_content_encoding
+                .as_ref()
+                .map(|encoding| HeaderValue::from_str(encoding))
+                .transpose()
+                .context("Invalid fake_content_encoding")?,
+            faults,
+            websocket: config
+                .websocket
+                .as_ref()
+                .map(WebSocketScript::from_config)
+                .transpose()?
+                .map(Arc::new),
+            sse: config
+                .sse
+                .as_ref()
+                .map(SseScript::from_config)
+                .map(Arc::new),
+        })
+    }
+}
+
+/// Load a body from a file, guessing its Content-Type unless one was given explicitly
+fn load_body_file(path: &Path, template: bool, headers: &mut HeaderMap) -> Result<ResponseBody> {
+    if !headers.contains_key(CONTENT_TYPE) {
+        if let Some(content_type) = files::guess_content_type(path) {
+            headers.insert(CONTENT_TYPE, content_type);
+        }
+    }
+
+    if template {
+        let body = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read body file '{}'", path.display()))?;
+        Ok(ResponseBody::Template(body))
+    } else {
+        let body = std::fs::read(path)
+            .with_context(|| format!("Failed to read body file '{}'", path.display()))?;
+        Ok(ResponseBody::Static(body.into()))
+    }
+}
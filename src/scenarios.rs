@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The state every scenario starts in and is reset to
+pub const STARTED: &str = "started";
+
+/// Current state of all scenarios
+///
+/// Routes belonging to a scenario only match while the scenario is in their required state and
+/// may move the scenario into a new state once they matched. This allows modelling sequences
+/// of responses such as a job that is pending for a while and done afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct Scenarios {
+    states: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl Scenarios {
+    pub fn new<I>(names: I) -> Scenarios
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let states = names
+            .into_iter()
+            .map(|name| (name, STARTED.to_string()))
+            .collect();
+        Scenarios {
+            states: Arc::new(Mutex::new(states)),
+        }
+    }
+
+    /// Add scenarios that are new after a reload, keeping the state of the existing ones
+    pub fn add<I>(&self, names: I)
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut states = self.lock();
+        for name in names {
+            states.entry(name).or_insert_with(|| STARTED.to_string());
+        }
+    }
+
+    /// Lock the states so that checking and transitioning a scenario happens atomically
+    pub fn lock(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+        self.states.lock().unwrap()
+    }
+
+    pub fn states(&self) -> BTreeMap<String, String> {
+        self.lock().clone()
+    }
+
+    /// Reset a single scenario, returning whether it exists
+    pub fn reset(&self, name: &str) -> bool {
+        match self.lock().get_mut(name) {
+            Some(state) => {
+                *state = STARTED.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn reset_all(&self) {
+        for state in self.lock().values_mut() {
+            *state = STARTED.to_string();
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::net::IpAddr;
+
+use anyhow::{Context, Result};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType, IsCa,
+    KeyUsagePurpose, SanType,
+};
+
+/// Names every self-signed certificate is valid for
+const DEFAULT_SANS: &[&str] = &["localhost", "127.0.0.1", "::1"];
+
+/// A certificate generated at startup for --tls-self-signed
+///
+/// Instead of a single self-signed certificate, a CA and a leaf certificate signed by it are
+/// generated. This way clients can trust the CA like they would trust a real one.
+#[derive(Debug, Clone)]
+pub struct SelfSigned {
+    /// The CA certificate as PEM
+    pub ca_pem: String,
+
+    /// SHA-256 fingerprint of the CA certificate (e.g. `AB:CD:...`)
+    pub ca_fingerprint: String,
+
+    /// The leaf certificate as PEM
+    pub cert_pem: String,
+
+    /// The private key of the leaf certificate as PEM
+    pub key_pem: String,
+}
+
+impl SelfSigned {
+    /// Generate a CA and a certificate valid for `DEFAULT_SANS` and `sans`
+    ///
+    /// Names that parse as IP addresses end up as IP SANs, everything else as DNS names.
+    pub fn generate(sans: &[String]) -> Result<SelfSigned> {
+        let mut ca_params = CertificateParams::new(vec![]);
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        ca_params.distinguished_name = distinguished_name("dummyhttp CA");
+        let ca = Certificate::from_params(ca_params).context("Failed to generate CA")?;
+
+        let mut names: Vec<&str> = DEFAULT_SANS.to_vec();
+        for san in sans {
+            if !names.contains(&san.as_str()) {
+                names.push(san);
+            }
+        }
+        let mut cert_params = CertificateParams::new(vec![]);
+        cert_params.subject_alt_names = names
+            .iter()
+            .map(|name| match name.parse::<IpAddr>() {
+                Ok(ip) => SanType::IpAddress(ip),
+                Err(_) => SanType::DnsName(name.to_string()),
+            })
+            .collect();
+        cert_params.distinguished_name = distinguished_name("dummyhttp");
+        let cert =
+            Certificate::from_params(cert_params).context("Failed to generate certificate")?;
+
+        // Every serialization signs the certificate anew, so the CA is serialized only once to
+        // make sure the fingerprint matches the PEM.
+        let ca_der = ca
+            .serialize_der()
+            .context("Failed to serialize CA certificate")?;
+        Ok(SelfSigned {
+            ca_pem: to_pem("CERTIFICATE", &ca_der),
+            ca_fingerprint: fingerprint(&ca_der),
+            cert_pem: cert
+                .serialize_pem_with_signer(&ca)
+                .context("Failed to sign certificate")?,
+            key_pem: cert.serialize_private_key_pem(),
+        })
+    }
+}
+
+fn distinguished_name(common_name: &str) -> DistinguishedName {
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, common_name);
+    name
+}
+
+/// Colon separated, uppercase hex SHA-256 digest as printed by `openssl x509 -fingerprint`
+pub fn fingerprint(der: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, der)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn to_pem(label: &str, der: &[u8]) -> String {
+    let encoded = base64::encode(der);
+    let mut pem = format!("-----BEGIN {label}-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {label}-----\n"));
+    pem
+}
@@ -0,0 +1,234 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Context, Result};
+use axum_server::Handle;
+use clap::Parser;
+
+use crate::args::Args;
+use crate::config::RouteConfig;
+use crate::journal::{Journal, JournalEntry, JournalFilter};
+use crate::listen::{Listen, ListenAddr, Listener};
+
+/// A dummyhttp server running in-process, e.g. for tests
+///
+/// The server runs on its own thread with its own runtime so it can be used from both sync and
+/// async tests. It is shut down once dropped or by `stop`, which also reports errors that made it
+/// stop serving.
+#[derive(Debug)]
+pub struct DummyServer {
+    addr: SocketAddr,
+    url: String,
+    journal: Journal,
+    #[cfg(feature = "tls")]
+    ca_certificate: Option<String>,
+    handle: Handle,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+/// What the server thread reports back once the server is set up
+struct Ready {
+    journal: Journal,
+    #[cfg(feature = "tls")]
+    ca_certificate: Option<String>,
+}
+
+/// Configures a `DummyServer`, see `DummyServer::builder`
+#[derive(Debug, Clone)]
+pub struct DummyServerBuilder {
+    args: Vec<String>,
+    routes: Vec<RouteConfig>,
+    interface: IpAddr,
+    port: u16,
+}
+
+impl DummyServer {
+    /// Start configuring a server
+    ///
+    /// By default, the server listens on an ephemeral port on 127.0.0.1 and answers all
+    /// requests just like `dummyhttp` without any arguments would.
+    pub fn builder() -> DummyServerBuilder {
+        DummyServerBuilder {
+            args: vec![],
+            routes: vec![],
+            interface: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 0,
+        }
+    }
+
+    /// The address the server is listening on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Base URL of the server without a trailing slash (e.g. `http://127.0.0.1:41234`)
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The requests received so far (up to `--journal-size`), oldest first
+    pub fn requests(&self) -> Vec<JournalEntry> {
+        self.journal.list(&JournalFilter::default())
+    }
+
+    /// The CA certificate (PEM) that signed the certificate generated for `--tls-self-signed`
+    ///
+    /// Add it to the root certificates of a client to have it trust the server.
+    #[cfg(feature = "tls")]
+    pub fn ca_certificate(&self) -> Option<&str> {
+        self.ca_certificate.as_deref()
+    }
+
+    /// Forget all requests received so far
+    pub fn clear_requests(&self) {
+        self.journal.clear();
+    }
+
+    /// Shut the server down, returning the error it failed with while serving, if any
+    pub fn stop(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.handle.shutdown();
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| anyhow!("Server thread panicked"))?
+                .context("Server failed"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for DummyServer {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+impl DummyServerBuilder {
+    /// Add a command line argument, all of dummyhttp's flags except `--port`, `--interface`
+    /// and `--listen` are supported
+    pub fn arg(mut self, arg: impl Into<String>) -> DummyServerBuilder {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add multiple command line arguments, see `arg`
+    pub fn args<I, S>(mut self, args: I) -> DummyServerBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Status code of the default response (same as `--code`)
+    pub fn code(self, code: u16) -> DummyServerBuilder {
+        self.args(["--code".to_string(), code.to_string()])
+    }
+
+    /// Header of the default response (same as `--headers`)
+    pub fn header(self, key: &str, value: &str) -> DummyServerBuilder {
+        self.args(["--headers".to_string(), format!("{key}:{value}")])
+    }
+
+    /// Body of the default response (same as `--body`)
+    pub fn body(self, body: impl Into<String>) -> DummyServerBuilder {
+        self.args(["--body".to_string(), body.into()])
+    }
+
+    /// Add a route, matched in order after the routes of a `--config` file
+    pub fn route(mut self, route: RouteConfig) -> DummyServerBuilder {
+        self.routes.push(route);
+        self
+    }
+
+    /// Interface to listen on, 127.0.0.1 by default
+    pub fn interface(mut self, interface: IpAddr) -> DummyServerBuilder {
+        self.interface = interface;
+        self
+    }
+
+    /// Port to listen on, 0 (the default) picks a free one
+    pub fn port(mut self, port: u16) -> DummyServerBuilder {
+        self.port = port;
+        self
+    }
+
+    /// Start the server, returning once it accepts connections
+    pub fn start(self) -> Result<DummyServer> {
+        let mut args = Args::try_parse_from(
+            std::iter::once("dummyhttp".to_string()).chain(self.args.iter().cloned()),
+        )
+        .context("Invalid arguments")?;
+        args.interface = self.interface;
+        args.port = self.port;
+
+        let listen = Listen {
+            addr: ListenAddr::Tcp(SocketAddr::from((args.interface, args.port))),
+            tls: args.tls(),
+            config: None,
+        };
+        let (listen, listener) = listen.bind(&args)?;
+        let listener = match listener {
+            Listener::Tcp(listener) => listener,
+            #[cfg(unix)]
+            Listener::Unix(_) => unreachable!("bound a TCP address"),
+        };
+        let addr = listener.local_addr()?;
+        let handle = Handle::new();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start runtime")?;
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = {
+            let handle = handle.clone();
+            let args = args.clone();
+            thread::spawn(move || {
+                runtime.block_on(async move {
+                    match crate::prepare(
+                        &args,
+                        vec![(listen, Listener::Tcp(listener))],
+                        self.routes,
+                        handle,
+                    )
+                    .await
+                    {
+                        Ok(server) => {
+                            let _ = ready_tx.send(Ok(Ready {
+                                journal: server.journal,
+                                #[cfg(feature = "tls")]
+                                ca_certificate: server.self_signed.map(|cert| cert.ca_pem),
+                            }));
+                            Ok(server.serve.await?)
+                        }
+                        Err(e) => {
+                            let _ = ready_tx.send(Err(e));
+                            Ok(())
+                        }
+                    }
+                })
+            })
+        };
+        let ready = ready_rx
+            .recv()
+            .map_err(|_| anyhow!("Server thread exited unexpectedly"))??;
+
+        let scheme = if args.tls() { "https" } else { "http" };
+        Ok(DummyServer {
+            addr,
+            url: format!("{scheme}://{addr}"),
+            journal: ready.journal,
+            #[cfg(feature = "tls")]
+            ca_certificate: ready.ca_certificate,
+            handle,
+            thread: Some(thread),
+        })
+    }
+}
@@ -0,0 +1,275 @@
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::{
+    body::{boxed, Body, BoxBody, Bytes, HttpBody},
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use axum_server::accept::{Accept, DefaultAcceptor};
+use axum_server::Handle;
+use futures_util::future::{self, BoxFuture, FutureExt};
+use futures_util::task::AtomicWaker;
+use hyper::body::SizeHint;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::watch;
+use tower_http::add_extension::AddExtension;
+
+/// Stops all listeners from accepting connections and lets the open ones finish
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    handle: Handle,
+
+    /// How long Unix socket listeners, which aren't served by axum-server, get to drain
+    drain: Arc<watch::Sender<Option<Duration>>>,
+
+    /// Unix socket files to remove once we stop accepting connections
+    socket_files: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl Shutdown {
+    pub fn new(handle: Handle) -> Shutdown {
+        Shutdown {
+            handle,
+            drain: Arc::new(watch::channel(None).0),
+            socket_files: Default::default(),
+        }
+    }
+
+    /// Remove the socket file at `path` on shutdown
+    ///
+    /// Only meant for sockets we bound ourselves, inherited ones belong to whoever created them.
+    pub fn remove_socket_file(&self, path: PathBuf) {
+        self.socket_files.lock().unwrap().push(path);
+    }
+
+    /// Stop accepting connections and give the open ones up to `timeout` to finish
+    pub fn graceful(&self, timeout: Duration) {
+        self.handle.graceful_shutdown(Some(timeout));
+        let _ = self.drain.send(Some(timeout));
+
+        // Connections which are already open keep working without the file.
+        for path in self.socket_files.lock().unwrap().drain(..) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Resolves to the drain timeout once `graceful` is called
+    pub fn requested(&self) -> BoxFuture<'static, Duration> {
+        let mut drain = self.drain.subscribe();
+        async move {
+            loop {
+                if let Some(timeout) = *drain.borrow() {
+                    return timeout;
+                }
+                if drain.changed().await.is_err() {
+                    // Nobody can request a shutdown anymore.
+                    future::pending::<()>().await;
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Middleware marking the connection of a request busy until its response is sent
+///
+/// This runs outside of all other middleware. While shutting down, `DrainStream` closes
+/// connections as soon as they aren't busy anymore instead of waiting for idle keep-alive
+/// connections to time out.
+pub async fn mark_busy(req: Request<Body>, next: Next<Body>) -> Response {
+    let busy = req
+        .extensions()
+        .get::<ConnectionActivity>()
+        .map(ConnectionActivity::busy);
+    let resp = next.run(req).await;
+    match busy {
+        Some(busy) => resp.map(|body| {
+            boxed(BusyBody {
+                inner: body,
+                _busy: busy,
+            })
+        }),
+        None => resp,
+    }
+}
+
+/// Whether a connection is in the middle of a request, shared by `mark_busy` and `DrainStream`
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionActivity(Arc<ActivityState>);
+
+#[derive(Debug, Default)]
+struct ActivityState {
+    /// Requests whose responses haven't been sent completely yet
+    responses: AtomicUsize,
+
+    /// Whether data was read that didn't make it to `mark_busy` yet, i.e. a request is coming in
+    reading: AtomicBool,
+
+    /// Woken once the last response is sent
+    idle: AtomicWaker,
+}
+
+impl ConnectionActivity {
+    fn busy(&self) -> BusyGuard {
+        self.0.responses.fetch_add(1, Ordering::SeqCst);
+        self.0.reading.store(false, Ordering::SeqCst);
+        BusyGuard(self.clone())
+    }
+
+    fn is_idle(&self) -> bool {
+        self.0.responses.load(Ordering::SeqCst) == 0 && !self.0.reading.load(Ordering::SeqCst)
+    }
+}
+
+/// Keeps a connection busy until dropped, see `mark_busy`
+#[derive(Debug)]
+struct BusyGuard(ConnectionActivity);
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        let state = &(self.0).0;
+        if state.responses.fetch_sub(1, Ordering::SeqCst) == 1 {
+            state.idle.wake();
+        }
+    }
+}
+
+/// A response body holding a `BusyGuard` until it is dropped by hyper after being sent
+struct BusyBody {
+    inner: BoxBody,
+    _busy: BusyGuard,
+}
+
+impl HttpBody for BusyBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, axum::Error>>> {
+        Pin::new(&mut self.inner).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, axum::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Acceptor wrapping every connection in a `DrainStream`
+///
+/// The `ConnectionActivity` of the connection is added to every request on it so that
+/// `mark_busy` can get hold of it.
+#[derive(Debug, Clone)]
+pub struct DrainAcceptor<A = DefaultAcceptor> {
+    inner: A,
+    shutdown: Shutdown,
+}
+
+impl DrainAcceptor {
+    pub fn new(shutdown: Shutdown) -> DrainAcceptor {
+        DrainAcceptor {
+            inner: DefaultAcceptor::new(),
+            shutdown,
+        }
+    }
+}
+
+impl<A, I, S> Accept<I, S> for DrainAcceptor<A>
+where
+    A: Accept<DrainStream<I>, AddExtension<S, ConnectionActivity>> + Clone + Send + 'static,
+    A::Future: Send,
+    I: Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let activity = ConnectionActivity::default();
+        let stream = DrainStream {
+            inner: stream,
+            activity: activity.clone(),
+            shutdown: Some(self.shutdown.requested()),
+        };
+        let service = AddExtension::new(service, activity);
+        let inner = self.inner.clone();
+        Box::pin(async move { inner.accept(stream, service).await })
+    }
+}
+
+/// A stream that ends reading once shutdown is requested and the connection is idle
+///
+/// hyper closes connections once reading ends, so idle keep-alive connections don't hold up the
+/// shutdown.
+pub struct DrainStream<I> {
+    inner: I,
+    activity: ConnectionActivity,
+
+    /// Resolves once shutdown is requested, `None` afterwards
+    shutdown: Option<BoxFuture<'static, Duration>>,
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for DrainStream<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let Some(shutdown) = &mut self.shutdown {
+            if shutdown.as_mut().poll(cx).is_ready() {
+                self.shutdown = None;
+            }
+        }
+        if self.shutdown.is_none() {
+            self.activity.0.idle.register(cx.waker());
+            if self.activity.is_idle() {
+                return Poll::Ready(Ok(()));
+            }
+        }
+
+        let filled = buf.filled().len();
+        futures_util::ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        if buf.filled().len() > filled {
+            self.activity.0.reading.store(true, Ordering::SeqCst);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for DrainStream<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use futures_util::stream;
+
+use crate::config::{EventConfig, SseConfig};
+use crate::delay::Delay;
+use crate::template;
+
+/// How many events to look through when resuming a repeating stream
+const MAX_RESUME_SCAN: usize = 10_000;
+
+/// How many event ids to render while resuming before letting other tasks run
+const RESUME_BATCH: usize = 100;
+
+/// A compiled `SseConfig`
+#[derive(Debug)]
+pub struct SseScript {
+    events: Vec<Event>,
+    repeat: bool,
+    resume: bool,
+}
+
+#[derive(Debug)]
+struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+    retry: Option<u64>,
+    delay: Option<Delay>,
+}
+
+impl SseScript {
+    pub fn from_config(config: &SseConfig) -> SseScript {
+        SseScript {
+            events: config.events.iter().map(Event::from_config).collect(),
+            repeat: config.repeat,
+            resume: config.resume,
+        }
+    }
+
+    /// Stream the events, continuing after `last_event_id` if resuming is enabled
+    ///
+    /// `context` is the Tera context of the request which `index` and `iteration` are added to
+    /// for every event. The event to resume after is looked up once the body is polled rather
+    /// than before the response is sent.
+    pub fn stream(self: Arc<Self>, context: tera::Context, last_event_id: Option<String>) -> Body {
+        let resume_after = last_event_id.filter(|_| self.resume);
+
+        let stream = stream::unfold(Some((0, resume_after)), move |state| {
+            let script = self.clone();
+            let context = context.clone();
+            async move {
+                let (mut index, resume_after) = state?;
+                if let Some(id) = resume_after {
+                    index = script.resume_index(&context, &id).await;
+                }
+                let event = script.event(index)?;
+                if let Some(delay) = &event.delay {
+                    tokio::time::sleep(delay.sample()).await;
+                }
+                let (chunk, next) = match event.render(&script.context(&context, index)) {
+                    Ok(chunk) => (chunk, Some((index + 1, None))),
+                    // Let the client know what went wrong with a comment and stop.
+                    Err(e) => (format!(": failed to render event: {e:?}\n\n"), None),
+                };
+                Some((Ok::<_, std::io::Error>(Bytes::from(chunk)), next))
+            }
+        });
+        Body::wrap_stream(stream)
+    }
+
+    /// The event at `index` in the stream, taking repetitions into account
+    fn event(&self, index: usize) -> Option<&Event> {
+        if self.events.is_empty() || (!self.repeat && index >= self.events.len()) {
+            return None;
+        }
+        Some(&self.events[index % self.events.len()])
+    }
+
+    fn context(&self, context: &tera::Context, index: usize) -> tera::Context {
+        let mut context = context.clone();
+        context.insert("index", &index);
+        context.insert("iteration", &(index / self.events.len().max(1)));
+        context
+    }
+
+    /// Index of the event following the one with the given id, or 0 if there is none
+    async fn resume_index(&self, context: &tera::Context, last_event_id: &str) -> usize {
+        let limit = if self.repeat {
+            MAX_RESUME_SCAN
+        } else {
+            self.events.len()
+        };
+        for index in 0..limit {
+            let matches = self
+                .event(index)
+                .and_then(|event| event.id.as_ref())
+                .and_then(|id| template::render(id, &self.context(context, index)).ok())
+                .is_some_and(|id| id == last_event_id);
+            if matches {
+                return index + 1;
+            }
+            if index % RESUME_BATCH == RESUME_BATCH - 1 {
+                tokio::task::yield_now().await;
+            }
+        }
+        0
+    }
+}
+
+impl Event {
+    fn from_config(config: &EventConfig) -> Event {
+        Event {
+            id: config.id.clone(),
+            event: config.event.clone(),
+            data: config.data.clone(),
+            retry: config.retry,
+            delay: config.delay.clone(),
+        }
+    }
+
+    /// Render the event in the `text/event-stream` format
+    fn render(&self, context: &tera::Context) -> tera::Result<String> {
+        let mut chunk = String::new();
+        if let Some(id) = &self.id {
+            writeln!(chunk, "id: {}", template::render(id, context)?).unwrap();
+        }
+        if let Some(event) = &self.event {
+            writeln!(chunk, "event: {}", template::render(event, context)?).unwrap();
+        }
+        if let Some(retry) = self.retry {
+            writeln!(chunk, "retry: {}", retry).unwrap();
+        }
+        // Events without a data line are dropped by clients, so empty data gets an empty line.
+        let data = template::render(&self.data, context)?;
+        for line in data.lines().chain(data.is_empty().then_some("")) {
+            writeln!(chunk, "data: {}", line).unwrap();
+        }
+        chunk.push('\n');
+        Ok(chunk)
+    }
+}
@@ -0,0 +1,179 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use axum::body::{Body, Bytes};
+use futures_util::stream;
+use serde::Deserialize;
+use tokio::time::Instant;
+
+use crate::delay::Delay;
+
+/// Chunk size used with a chunk delay but no explicit chunk size
+const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// How a body is sent if not all at once
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transfer {
+    /// Spread the body evenly over a duration
+    Trickle(Delay),
+
+    /// Send the body with chunked transfer encoding, waiting `delay` between chunks
+    Chunked { size: usize, delay: Option<Delay> },
+
+    /// Send the body no faster than the given rate
+    Bandwidth(Bandwidth),
+}
+
+impl Transfer {
+    /// Combine the streaming settings of a route, of which at most one kind may be given
+    pub fn from_settings(
+        trickle: Option<&Delay>,
+        chunk_size: Option<usize>,
+        chunk_delay: Option<&Delay>,
+        bandwidth: Option<Bandwidth>,
+    ) -> Result<Option<Transfer>> {
+        let chunked = (chunk_size.is_some() || chunk_delay.is_some()).then(|| Transfer::Chunked {
+            size: chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE),
+            delay: chunk_delay.cloned(),
+        });
+        let mut transfers = trickle
+            .cloned()
+            .map(Transfer::Trickle)
+            .into_iter()
+            .chain(chunked)
+            .chain(bandwidth.map(Transfer::Bandwidth));
+        let transfer = transfers.next();
+        if transfers.next().is_some() {
+            bail!("Only one of trickle, chunk_size/chunk_delay and bandwidth can be given");
+        }
+        if let Some(Transfer::Chunked { size: 0, .. }) = transfer {
+            bail!("Chunk size must be greater than zero");
+        }
+        Ok(transfer)
+    }
+
+    /// Whether the body is sent with its Content-Length known up front
+    pub fn has_content_length(&self) -> bool {
+        !matches!(self, Transfer::Chunked { .. })
+    }
+
+    pub fn stream(&self, body: Bytes) -> Body {
+        match self {
+            Transfer::Trickle(duration) => trickle(body, duration.sample()),
+            Transfer::Chunked { size, delay } => chunked(body, *size, delay.clone()),
+            Transfer::Bandwidth(bandwidth) => throttle(body, *bandwidth),
+        }
+    }
+}
+
+/// A transfer rate in bytes per second
+///
+/// Given as a number of bytes optionally followed by `k` (KiB) or `m` (MiB), e.g. `512`, `64k`
+/// or `1m`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Bandwidth(pub u64);
+
+impl FromStr for Bandwidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Bandwidth, String> {
+        let s = s.trim();
+        let (number, multiplier) = match s.char_indices().last() {
+            Some((i, 'k' | 'K')) => (&s[..i], 1024),
+            Some((i, 'm' | 'M')) => (&s[..i], 1024 * 1024),
+            _ => (s, 1),
+        };
+        let bytes = number
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid bandwidth '{}'", s))?;
+        if bytes == 0 {
+            return Err("Bandwidth must be greater than zero".to_string());
+        }
+        bytes
+            .checked_mul(multiplier)
+            .map(Bandwidth)
+            .ok_or_else(|| format!("Bandwidth '{}' is too large", s))
+    }
+}
+
+impl TryFrom<String> for Bandwidth {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Bandwidth, String> {
+        s.parse()
+    }
+}
+
+/// Spread a body evenly over the given duration
+///
+/// The body is sent in at most one chunk every 10ms so that slow clients see data trickle in
+/// without us flooding the connection with tiny writes.
+fn trickle(body: Bytes, duration: Duration) -> Body {
+    let max_chunks = (duration.as_millis() / 10).max(1) as usize;
+    let n_chunks = body.len().clamp(1, max_chunks);
+    let chunk_size = (body.len() + n_chunks - 1).max(1) / n_chunks;
+    let interval = duration / n_chunks as u32;
+
+    let chunks = (0..n_chunks)
+        .map(|i| {
+            let start = (i * chunk_size).min(body.len());
+            let end = if i == n_chunks - 1 {
+                body.len()
+            } else {
+                ((i + 1) * chunk_size).min(body.len())
+            };
+            body.slice(start..end)
+        })
+        .collect::<Vec<_>>();
+
+    let stream = stream::unfold(chunks.into_iter(), move |mut chunks| async move {
+        let chunk = chunks.next()?;
+        tokio::time::sleep(interval).await;
+        Some((Ok::<_, std::io::Error>(chunk), chunks))
+    });
+    Body::wrap_stream(stream)
+}
+
+/// Send a body in chunks of `size` bytes, sampling `delay` before every chunk but the first
+fn chunked(body: Bytes, size: usize, delay: Option<Delay>) -> Body {
+    let stream = stream::unfold(0, move |start| {
+        let body = body.clone();
+        let delay = delay.clone();
+        async move {
+            if start >= body.len() {
+                return None;
+            }
+            if let (true, Some(delay)) = (start > 0, delay) {
+                tokio::time::sleep(delay.sample()).await;
+            }
+            let end = (start + size).min(body.len());
+            Some((Ok::<_, std::io::Error>(body.slice(start..end)), end))
+        }
+    });
+    Body::wrap_stream(stream)
+}
+
+/// Send a body no faster than `bandwidth`
+///
+/// Like `trickle`, this sends at most one chunk every 10ms. Every chunk waits until the bytes
+/// sent before it would have taken that long at the given rate.
+fn throttle(body: Bytes, bandwidth: Bandwidth) -> Body {
+    let chunk_size = (bandwidth.0 / 100).max(1) as usize;
+    let start_time = Instant::now();
+    let stream = stream::unfold(0, move |start| {
+        let body = body.clone();
+        async move {
+            if start >= body.len() {
+                return None;
+            }
+            let due = Duration::from_secs_f64(start as f64 / bandwidth.0 as f64);
+            tokio::time::sleep_until(start_time + due).await;
+            let end = (start + chunk_size).min(body.len());
+            Some((Ok::<_, std::io::Error>(body.slice(start..end)), end))
+        }
+    });
+    Body::wrap_stream(stream)
+}
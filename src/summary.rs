@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+/// How many unmatched and failed requests are listed individually, more are only counted
+const MAX_LISTED: usize = 100;
+
+/// Counts of what dummyhttp served, reported on shutdown
+#[derive(Debug, Clone, Default)]
+pub struct Summary(Arc<Mutex<Totals>>);
+
+/// A snapshot of the `Summary`, as printed or written to --summary-file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Totals {
+    pub requests: u64,
+
+    /// Requests per route name, with `default` for the default response
+    pub routes: BTreeMap<String, u64>,
+
+    pub unmatched: Listed<UnmatchedRequest>,
+    pub errors: Listed<FailedRequest>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Listed<T> {
+    pub count: u64,
+
+    /// The first `MAX_LISTED` requests
+    pub requests: Vec<T>,
+}
+
+/// A request that didn't match any route
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmatchedRequest {
+    pub method: String,
+    pub uri: String,
+    pub reason: String,
+}
+
+/// A request dummyhttp failed to answer as configured, e.g. because a template didn't render
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedRequest {
+    pub method: String,
+    pub uri: String,
+    pub status: u16,
+    pub message: String,
+}
+
+impl Summary {
+    /// Count a request answered by `route`
+    pub fn record_request(&self, route: &str) {
+        let mut totals = self.0.lock().unwrap();
+        totals.requests += 1;
+        *totals.routes.entry(route.to_string()).or_default() += 1;
+    }
+
+    pub fn record_unmatched(&self, request: UnmatchedRequest) {
+        self.0.lock().unwrap().unmatched.push(request);
+    }
+
+    pub fn record_error(&self, request: FailedRequest) {
+        self.0.lock().unwrap().errors.push(request);
+    }
+
+    pub fn totals(&self) -> Totals {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl<T> Default for Listed<T> {
+    fn default() -> Listed<T> {
+        Listed {
+            count: 0,
+            requests: vec![],
+        }
+    }
+}
+
+impl<T> Listed<T> {
+    fn push(&mut self, request: T) {
+        self.count += 1;
+        if self.requests.len() < MAX_LISTED {
+            self.requests.push(request);
+        }
+    }
+
+    /// How many requests were counted but not listed
+    fn omitted(&self) -> u64 {
+        self.count - self.requests.len() as u64
+    }
+}
+
+impl Totals {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize summary")
+    }
+
+    /// Human readable summary over multiple lines
+    pub fn to_pretty(&self) -> String {
+        let mut out = format!(
+            "Served {} requests, {} unmatched, {} failed",
+            self.requests, self.unmatched.count, self.errors.count
+        );
+        for (route, count) in &self.routes {
+            let _ = write!(out, "\n  {count:>6} {route}");
+        }
+        if self.unmatched.count > 0 {
+            out.push_str("\nUnmatched requests:");
+            for request in &self.unmatched.requests {
+                let _ = write!(
+                    out,
+                    "\n  {} {} - {}",
+                    request.method, request.uri, request.reason
+                );
+            }
+            if self.unmatched.omitted() > 0 {
+                let _ = write!(out, "\n  ... and {} more", self.unmatched.omitted());
+            }
+        }
+        if self.errors.count > 0 {
+            out.push_str("\nFailed requests:");
+            for request in &self.errors.requests {
+                let _ = write!(
+                    out,
+                    "\n  {} {} {} - {}",
+                    request.method, request.uri, request.status, request.message
+                );
+            }
+            if self.errors.omitted() > 0 {
+                let _ = write!(out, "\n  ... and {} more", self.errors.omitted());
+            }
+        }
+        out
+    }
+
+    /// Write the JSON summary to `path`
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json() + "\n")
+            .with_context(|| format!("Failed to write summary to '{}'", path.display()))
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::http::{HeaderMap, Method, Uri};
+use serde::Serialize;
+
+use crate::peer::Peer;
+use crate::tls::ClientCertificate;
+
+pub fn template_uuid(_args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    Ok(tera::to_value(uuid::Uuid::new_v4().to_string()).unwrap())
+}
+
+pub fn template_lorem(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let n_words = args
+        .get("words")
+        .and_then(|w| w.as_u64())
+        .ok_or_else(|| tera::Error::from("Failed to template lorem"))?;
+    Ok(tera::to_value(lipsum::lipsum(n_words as usize)).unwrap())
+}
+
+/// Render a body template with the additional dummyhttp functions
+pub fn render(template: &str, context: &tera::Context) -> tera::Result<String> {
+    let mut tera = tera::Tera::default();
+    tera.register_function("uuid", template_uuid);
+    tera.register_function("lorem", template_lorem);
+    tera.render_str(template, context)
+}
+
+/// Flatten headers into a map with lowercase names, joining repeated headers with ", "
+pub fn header_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut header_map = BTreeMap::<String, String>::new();
+    for (key, value) in headers {
+        let value = value.to_str().unwrap_or("<unprintable>");
+        header_map
+            .entry(key.as_str().to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+    header_map
+}
+
+/// The incoming request as exposed to body templates as `request`
+#[derive(Debug, Serialize)]
+pub struct RequestContext {
+    pub method: String,
+    pub path: String,
+
+    /// Query parameters, only the first value is kept for repeated keys
+    pub query: BTreeMap<String, String>,
+
+    /// Headers with lowercase names, repeated headers are joined with ", "
+    pub headers: BTreeMap<String, String>,
+
+    /// Raw body
+    pub body: String,
+
+    /// Body parsed as JSON or `null` if it isn't valid JSON
+    pub json: serde_json::Value,
+
+    pub peer: String,
+
+    /// Path segments captured by `:name` and `*name` placeholders of the matched route
+    pub params: BTreeMap<String, String>,
+
+    /// Certificate presented by the client when using --tls-client-ca
+    pub client_cert: Option<ClientCertificate>,
+}
+
+impl RequestContext {
+    pub fn new(
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        body: &[u8],
+        peer: Peer,
+        params: BTreeMap<String, String>,
+        client_cert: Option<ClientCertificate>,
+    ) -> RequestContext {
+        let mut query = BTreeMap::new();
+        let pairs = serde_urlencoded::from_str::<Vec<(String, String)>>(uri.query().unwrap_or(""))
+            .unwrap_or_default();
+        for (key, value) in pairs {
+            query.entry(key).or_insert(value);
+        }
+
+        RequestContext {
+            method: method.to_string(),
+            path: uri.path().to_string(),
+            query,
+            headers: header_map(headers),
+            body: String::from_utf8_lossy(body).to_string(),
+            json: serde_json::from_slice(body).unwrap_or(serde_json::Value::Null),
+            peer: peer.to_string(),
+            params,
+            client_cert,
+        }
+    }
+
+    /// Turn this into a Tera context with the request available as `request`
+    pub fn into_tera_context(self) -> tera::Context {
+        let mut context = tera::Context::new();
+        context.insert("request", &self);
+        context
+    }
+}
@@ -0,0 +1,216 @@
+use axum::http::Extensions;
+use serde::Serialize;
+
+#[cfg(feature = "tls")]
+pub use self::server::*;
+
+/// A certificate presented by a client, as logged and exposed to body templates as
+/// `request.client_cert`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClientCertificate {
+    /// Subject of the certificate (e.g. `CN=client, O=Example`)
+    pub subject: String,
+
+    /// Subject alternative names, prefixed with their type like OpenSSL does (e.g.
+    /// `DNS:client.example.com` or `IP:127.0.0.1`)
+    pub sans: Vec<String>,
+
+    /// SHA-256 fingerprint of the certificate
+    pub fingerprint: String,
+}
+
+/// The client certificate of the connection a request came in on, if any
+pub fn client_certificate(extensions: &Extensions) -> Option<ClientCertificate> {
+    extensions
+        .get::<Option<ClientCertificate>>()
+        .cloned()
+        .flatten()
+}
+
+#[cfg(feature = "tls")]
+mod server {
+    use std::convert::TryFrom;
+    use std::fs;
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::sync::Arc;
+
+    use anyhow::{anyhow, bail, Context, Result};
+    use axum_server::accept::Accept;
+    use clap::ArgEnum;
+    use futures_util::future::BoxFuture;
+    use rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient};
+    use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+    use tokio_rustls::server::TlsStream;
+    use tower_http::add_extension::AddExtension;
+    use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+    use super::ClientCertificate;
+    use crate::args::Args;
+    use crate::self_signed::{fingerprint, SelfSigned};
+
+    /// Whether clients have to present a certificate when --tls-client-ca is given
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+    pub enum ClientAuth {
+        /// Reject clients without a valid certificate
+        Required,
+
+        /// Accept clients without a certificate, but reject invalid ones
+        Optional,
+    }
+
+    /// Build the rustls config serving either the --tls-cert/--tls-key files or `self_signed`
+    ///
+    /// If --tls-client-ca is given, client certificates are verified against it.
+    pub fn server_config(
+        args: &Args,
+        self_signed: Option<&SelfSigned>,
+    ) -> Result<Arc<ServerConfig>> {
+        let (certs, key) = match (self_signed, &args.tls_cert, &args.tls_key) {
+            (Some(self_signed), _, _) => parse_identity(
+                self_signed.cert_pem.as_bytes(),
+                self_signed.key_pem.as_bytes(),
+            )
+            .context("Failed to load self-signed certificate")?,
+            (None, Some(tls_cert), Some(tls_key)) => fs::read(tls_cert)
+                .and_then(|cert| Ok((cert, fs::read(tls_key)?)))
+                .map_err(anyhow::Error::from)
+                .and_then(|(cert, key)| parse_identity(&cert, &key))
+                .with_context(|| {
+                    format!(
+                        "Failed to load certificate file '{}' or key '{}'",
+                        tls_cert.to_string_lossy(),
+                        tls_key.to_string_lossy()
+                    )
+                })?,
+            _ => bail!("No TLS certificate configured"),
+        };
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &args.tls_client_ca {
+            Some(path) => {
+                let roots = fs::read(path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|pem| parse_roots(&pem))
+                    .with_context(|| {
+                        format!("Failed to load client CA '{}'", path.to_string_lossy())
+                    })?;
+                let verifier = match args.tls_client_auth {
+                    ClientAuth::Required => AllowAnyAuthenticatedClient::new(roots),
+                    ClientAuth::Optional => AllowAnyAnonymousOrAuthenticatedClient::new(roots),
+                };
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .context("Invalid TLS certificate or key")?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
+    }
+
+    fn parse_identity(cert: &[u8], key: &[u8]) -> Result<(Vec<Certificate>, PrivateKey)> {
+        use rustls_pemfile::Item;
+
+        let certs = rustls_pemfile::certs(&mut &*cert)?;
+        if certs.is_empty() {
+            bail!("No certificate found");
+        }
+        let key = match rustls_pemfile::read_one(&mut &*key)? {
+            Some(Item::RSAKey(key)) | Some(Item::PKCS8Key(key)) | Some(Item::ECKey(key)) => key,
+            _ => bail!("Private key format not supported"),
+        };
+        Ok((
+            certs.into_iter().map(Certificate).collect(),
+            PrivateKey(key),
+        ))
+    }
+
+    fn parse_roots(pem: &[u8]) -> Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut &*pem)? {
+            roots.add(&Certificate(cert))?;
+        }
+        if roots.is_empty() {
+            bail!("No certificate found");
+        }
+        Ok(roots)
+    }
+
+    impl ClientCertificate {
+        pub fn from_der(der: &[u8]) -> Result<ClientCertificate> {
+            let (_, cert) = X509Certificate::from_der(der)
+                .map_err(|e| anyhow!("Invalid client certificate: {e}"))?;
+            let sans = match cert.subject_alternative_name() {
+                Ok(Some(san)) => san.value.general_names.iter().map(format_san).collect(),
+                _ => vec![],
+            };
+            Ok(ClientCertificate {
+                subject: cert.subject().to_string(),
+                sans,
+                fingerprint: fingerprint(der),
+            })
+        }
+    }
+
+    fn format_san(name: &GeneralName) -> String {
+        match name {
+            GeneralName::DNSName(name) => format!("DNS:{name}"),
+            GeneralName::RFC822Name(email) => format!("email:{email}"),
+            GeneralName::URI(uri) => format!("URI:{uri}"),
+            GeneralName::IPAddress(bytes) => {
+                let ip = match bytes.len() {
+                    4 => <[u8; 4]>::try_from(*bytes).map(|b| IpAddr::from(Ipv4Addr::from(b))),
+                    _ => <[u8; 16]>::try_from(*bytes).map(|b| IpAddr::from(Ipv6Addr::from(b))),
+                };
+                match ip {
+                    Ok(ip) => format!("IP:{ip}"),
+                    Err(_) => name.to_string(),
+                }
+            }
+            other => other.to_string(),
+        }
+    }
+
+    /// Acceptor adding the certificate presented by the client to every request on a connection
+    ///
+    /// This wraps the rustls acceptor as the certificate is only known after the handshake.
+    #[derive(Debug, Clone)]
+    pub struct ClientCertAcceptor<A> {
+        inner: A,
+    }
+
+    impl<A> ClientCertAcceptor<A> {
+        pub fn new(inner: A) -> ClientCertAcceptor<A> {
+            ClientCertAcceptor { inner }
+        }
+    }
+
+    impl<A, I, S, T> Accept<I, S> for ClientCertAcceptor<A>
+    where
+        A: Accept<I, S, Stream = TlsStream<T>>,
+        A::Future: Send + 'static,
+        A::Service: Send + 'static,
+        T: Send + 'static,
+    {
+        type Stream = A::Stream;
+        type Service = AddExtension<A::Service, Option<ClientCertificate>>;
+        type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+        fn accept(&self, stream: I, service: S) -> Self::Future {
+            let accept = self.inner.accept(stream, service);
+            Box::pin(async move {
+                let (stream, service) = accept.await?;
+                let cert = stream
+                    .get_ref()
+                    .1
+                    .peer_certificates()
+                    .and_then(|certs| certs.first())
+                    .and_then(|cert| ClientCertificate::from_der(&cert.0).ok());
+                Ok((stream, AddExtension::new(service, cert)))
+            })
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::borrow::Cow;
+
+use anyhow::{bail, Context, Result};
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use chrono::Local;
+use colored::*;
+use serde_json::Value;
+
+use crate::args::Args;
+use crate::config::{CloseConfig, MessageConfig, ReplyConfig, WebSocketConfig};
+use crate::delay::Delay;
+use crate::logging::{FrameRecord, LogFormat};
+use crate::matching::BodyMatcher;
+use crate::peer::Peer;
+use crate::template;
+
+/// A compiled `WebSocketConfig`
+#[derive(Debug)]
+pub struct WebSocketScript {
+    send: Vec<ScriptMessage>,
+    replies: Vec<Reply>,
+    echo: bool,
+    close: Option<Close>,
+}
+
+#[derive(Debug)]
+struct ScriptMessage {
+    body: MessageBody,
+    delay: Option<Delay>,
+}
+
+#[derive(Debug)]
+enum MessageBody {
+    /// Rendered as Tera template before sending
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug)]
+struct Reply {
+    matchers: Vec<BodyMatcher>,
+    messages: Vec<ScriptMessage>,
+    close: Option<Close>,
+}
+
+#[derive(Debug)]
+struct Close {
+    code: u16,
+    reason: String,
+    delay: Option<Delay>,
+}
+
+impl WebSocketScript {
+    pub fn from_config(config: &WebSocketConfig) -> Result<WebSocketScript> {
+        Ok(WebSocketScript {
+            send: config
+                .send
+                .iter()
+                .map(ScriptMessage::from_config)
+                .collect::<Result<_>>()?,
+            replies: config
+                .on_message
+                .iter()
+                .map(Reply::from_config)
+                .collect::<Result<_>>()?,
+            echo: config.echo,
+            close: config.close.as_ref().map(Close::from_config),
+        })
+    }
+}
+
+impl ScriptMessage {
+    fn from_config(config: &MessageConfig) -> Result<ScriptMessage> {
+        let body = match (&config.text, &config.binary) {
+            (Some(text), None) => MessageBody::Text(text.clone()),
+            (None, Some(binary)) => MessageBody::Binary(
+                base64::decode(binary).context("Binary WebSocket message is not valid base64")?,
+            ),
+            _ => bail!("WebSocket messages need exactly one of text or binary"),
+        };
+        Ok(ScriptMessage {
+            body,
+            delay: config.delay.clone(),
+        })
+    }
+}
+
+impl Reply {
+    fn from_config(config: &ReplyConfig) -> Result<Reply> {
+        Ok(Reply {
+            matchers: config
+                .matches
+                .clone()
+                .map(|matches| matches.into_vec())
+                .unwrap_or_default()
+                .iter()
+                .map(BodyMatcher::from_config)
+                .collect::<Result<_>>()?,
+            messages: config
+                .reply
+                .clone()
+                .map(|reply| reply.into_vec())
+                .unwrap_or_default()
+                .iter()
+                .map(ScriptMessage::from_config)
+                .collect::<Result<_>>()?,
+            close: config.close.as_ref().map(Close::from_config),
+        })
+    }
+
+    fn matches(&self, message: &str) -> bool {
+        let json = serde_json::from_str::<Value>(message).ok();
+        self.matchers
+            .iter()
+            .all(|matcher| matcher.check(message, json.as_ref()).is_ok())
+    }
+}
+
+impl Close {
+    fn from_config(config: &CloseConfig) -> Close {
+        Close {
+            code: config.code,
+            reason: config.reason.clone(),
+            delay: config.delay.clone(),
+        }
+    }
+}
+
+/// A WebSocket connection following a `WebSocketScript`
+pub struct Connection {
+    socket: WebSocket,
+    args: Args,
+    peer: Peer,
+    uri: String,
+
+    /// Tera context with the upgrade request available as `request`
+    context: tera::Context,
+}
+
+impl Connection {
+    pub fn new(
+        socket: WebSocket,
+        args: Args,
+        peer: Peer,
+        uri: String,
+        context: tera::Context,
+    ) -> Connection {
+        Connection {
+            socket,
+            args,
+            peer,
+            uri,
+            context,
+        }
+    }
+
+    /// Follow the script until either side closes the connection
+    pub async fn run(mut self, script: &WebSocketScript) {
+        if let Err(e) = self.follow(script).await {
+            self.log_error(&e);
+        }
+    }
+
+    async fn follow(&mut self, script: &WebSocketScript) -> Result<()> {
+        let context = self.context.clone();
+        for message in &script.send {
+            self.send(message, &context).await?;
+        }
+        if let Some(close) = &script.close {
+            return self.close(close).await;
+        }
+
+        while let Some(message) = self.socket.recv().await {
+            let message = message?;
+            self.log_frame("received", &message);
+            let text = match &message {
+                Message::Text(text) => text.clone(),
+                Message::Binary(data) => String::from_utf8_lossy(data).to_string(),
+                Message::Ping(_) | Message::Pong(_) => continue,
+                Message::Close(_) => break,
+            };
+
+            match script.replies.iter().find(|reply| reply.matches(&text)) {
+                Some(reply) => {
+                    let mut context = self.context.clone();
+                    context.insert("message", &text);
+                    for message in &reply.messages {
+                        self.send(message, &context).await?;
+                    }
+                    if let Some(close) = &reply.close {
+                        return self.close(close).await;
+                    }
+                }
+                None if script.echo => {
+                    self.log_frame("sent", &message);
+                    self.socket.send(message).await?;
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, message: &ScriptMessage, context: &tera::Context) -> Result<()> {
+        if let Some(delay) = &message.delay {
+            tokio::time::sleep(delay.sample()).await;
+        }
+        let message = match &message.body {
+            MessageBody::Text(text) => Message::Text(
+                template::render(text, context)
+                    .context("Failed to render WebSocket message template")?,
+            ),
+            MessageBody::Binary(data) => Message::Binary(data.clone()),
+        };
+        self.log_frame("sent", &message);
+        self.socket.send(message).await?;
+        Ok(())
+    }
+
+    async fn close(&mut self, close: &Close) -> Result<()> {
+        if let Some(delay) = &close.delay {
+            tokio::time::sleep(delay.sample()).await;
+        }
+        let message = Message::Close(Some(CloseFrame {
+            code: close.code,
+            reason: Cow::Owned(close.reason.clone()),
+        }));
+        self.log_frame("sent", &message);
+        self.socket.send(message).await?;
+
+        // Wait for the client to acknowledge the close frame.
+        while let Some(Ok(message)) = self.socket.recv().await {
+            self.log_frame("received", &message);
+        }
+        Ok(())
+    }
+
+    fn log_frame(&self, direction: &'static str, message: &Message) {
+        if self.args.quiet {
+            return;
+        }
+        let (frame, data) = match message {
+            Message::Text(text) => ("text", text.clone()),
+            Message::Binary(data) => ("binary", base64::encode(data)),
+            Message::Ping(data) => ("ping", base64::encode(data)),
+            Message::Pong(data) => ("pong", base64::encode(data)),
+            Message::Close(Some(close)) => ("close", format!("{} {}", close.code, close.reason)),
+            Message::Close(None) => ("close", String::new()),
+        };
+
+        match self.args.log_format {
+            LogFormat::Pretty => {
+                let arrow = match direction {
+                    "received" => "ws <-".green(),
+                    _ => "ws ->".red(),
+                };
+                println!(
+                    "{time} {peer} {uri} {arrow} {frame} {data}",
+                    time = Local::now()
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                        .yellow(),
+                    peer = self.peer.to_string().bold(),
+                    uri = self.uri.cyan().underline(),
+                    arrow = arrow.bold(),
+                    frame = frame.blue(),
+                    data = data,
+                );
+            }
+            format => {
+                let record = FrameRecord {
+                    timestamp: Local::now().to_rfc3339(),
+                    peer: self.peer.to_string(),
+                    uri: self.uri.clone(),
+                    direction,
+                    frame,
+                    data,
+                };
+                match format {
+                    LogFormat::Json => println!("{}", record.to_json()),
+                    LogFormat::Logfmt => println!("{}", record.to_logfmt()),
+                    LogFormat::Pretty => unreachable!(),
+                }
+            }
+        }
+    }
+
+    fn log_error(&self, error: &anyhow::Error) {
+        if !self.args.quiet {
+            eprintln!(
+                "WebSocket connection to {} on {} failed: {error:#}",
+                self.peer, self.uri
+            );
+        }
+    }
+}
@@ -0,0 +1,99 @@
+mod utils;
+
+use axum::http::StatusCode;
+use reqwest::blocking::Client;
+use serde_json::Value;
+use utils::{DummyhttpProcess, Error};
+
+/// Requests are recorded in the journal and can be listed and filtered.
+#[test]
+fn lists_recorded_requests() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(Vec::<String>::new())?;
+
+    let client = Client::new();
+    client
+        .post(format!("{}/jobs?a=b", dh.url))
+        .header("x-request-id", "1")
+        .body("first")
+        .send()?;
+    client
+        .post(format!("{}/jobs", dh.url))
+        .header("x-request-id", "2")
+        .body("second")
+        .send()?;
+    client.get(format!("{}/other", dh.url)).send()?;
+
+    let resp = client
+        .get(format!("{}/__dummyhttp/requests", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let requests: Vec<Value> = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0]["method"], "POST");
+    assert_eq!(requests[0]["path"], "/jobs");
+    assert_eq!(requests[0]["query"], "a=b");
+    assert_eq!(requests[0]["body"], "first");
+    assert_eq!(requests[0]["status"], 200);
+    assert_eq!(requests[2]["path"], "/other");
+
+    let resp = client
+        .get(format!(
+            "{}/__dummyhttp/requests?method=post&header=x-request-id:2",
+            dh.url
+        ))
+        .send()?;
+    let requests: Vec<Value> = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["body"], "second");
+
+    // Whitespace around the header name and value is ignored.
+    let resp = client
+        .get(format!(
+            "{}/__dummyhttp/requests?header=%20X-Request-Id%20:%202%20",
+            dh.url
+        ))
+        .send()?;
+    let requests: Vec<Value> = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["body"], "second");
+
+    Ok(())
+}
+
+/// The journal can be counted and cleared.
+#[test]
+fn counts_and_clears_recorded_requests() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(Vec::<String>::new())?;
+
+    let client = Client::new();
+    client.get(format!("{}/a", dh.url)).send()?;
+    client.get(format!("{}/a", dh.url)).send()?;
+    client.get(format!("{}/b", dh.url)).send()?;
+
+    let count_url = format!("{}/__dummyhttp/requests/count?path=/a", dh.url);
+    let count: Value = serde_json::from_str(&client.get(&count_url).send()?.text()?)?;
+    assert_eq!(count["count"], 2);
+
+    let resp = client
+        .delete(format!("{}/__dummyhttp/requests", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let count: Value = serde_json::from_str(&client.get(&count_url).send()?.text()?)?;
+    assert_eq!(count["count"], 0);
+
+    Ok(())
+}
+
+/// Unknown filters are rejected.
+#[test]
+fn rejects_unknown_filters() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(Vec::<String>::new())?;
+
+    let resp = Client::new()
+        .get(format!("{}/__dummyhttp/requests?nope=1", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+mod utils;
+
+use assert_cmd::prelude::*;
+use axum::http::StatusCode;
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+use utils::{DummyhttpProcess, Error};
+
+/// A status fault replaces the status code and is logged.
+#[test]
+fn injects_status() -> Result<(), Error> {
+    let mut dh = DummyhttpProcess::new(vec!["--fault", "status=503"])?;
+
+    let resp = Client::new().get(&dh.url).send()?;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    dh.child.kill()?;
+    let mut output = String::new();
+    dh.child
+        .stdout
+        .as_mut()
+        .unwrap()
+        .read_to_string(&mut output)?;
+    assert!(output.contains("fault:"));
+    assert!(output.contains("status=503"));
+
+    Ok(())
+}
+
+/// Connection level faults make the request fail on the client side.
+#[rstest(
+    fault,
+    case::reset("reset"),
+    case::close("close"),
+    case::malformed("malformed")
+)]
+fn injects_connection_faults(fault: &'static str) -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--fault", fault])?;
+
+    let result = Client::new()
+        .get(&dh.url)
+        .send()
+        .and_then(|resp| resp.text());
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+/// A truncated body is shorter than the announced Content-Length.
+#[test]
+fn injects_truncated_body() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--fault", "truncate", "-b", "hello world body"])?;
+
+    let resp = Client::new().get(&dh.url).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("content-length").unwrap(), "16");
+    assert!(resp.text().is_err());
+
+    // The first half of the body is sent before the connection is closed.
+    let mut stream = TcpStream::connect(format!("localhost:{}", dh.port))?;
+    write!(
+        stream,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut response = vec![];
+    let _ = stream.read_to_end(&mut response);
+    assert!(String::from_utf8_lossy(&response).ends_with("\r\n\r\nhello wo"));
+
+    Ok(())
+}
+
+/// Faults with a probability of 0 are never injected and routes can override faults.
+#[test]
+fn respects_probability_and_routes() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "--fault",
+        "close@0",
+        "--config",
+        "tests/data/faults.yaml",
+    ])?;
+
+    let client = Client::new();
+    for _ in 0..10 {
+        let resp = client.get(&dh.url).send()?;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let resp = client.get(format!("{}/flaky", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let resp = client.get(format!("{}/stable", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+/// Invalid faults are rejected.
+#[rstest(
+    fault,
+    case::unknown("explode"),
+    case::bad_status("status=abc"),
+    case::bad_probability("reset@2")
+)]
+fn invalid_fault_fails(fault: &'static str) -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--fault", fault])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid value"));
+
+    Ok(())
+}
+
+/// At most one fault is picked per response, so their probabilities can't exceed 1 in total.
+#[test]
+fn excessive_probabilities_fail() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--fault", "status=503@0.6", "--fault", "reset@0.6"])
+        .assert()
+        .failure()
+        .stderr(contains("Fault probabilities add up to 1.2"));
+
+    // Rounding errors are tolerated.
+    DummyhttpProcess::new(vec![
+        "--fault",
+        "status=503@0.33",
+        "--fault",
+        "status=502@0.56",
+        "--fault",
+        "status=500@0.11",
+    ])?;
+
+    Ok(())
+}
@@ -0,0 +1,207 @@
+mod utils;
+
+use std::io::{Read, Write};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use utils::{DummyhttpProcess, Error};
+
+fn decode(encoding: &str, body: &[u8]) -> Result<String, Error> {
+    let mut decoded = String::new();
+    match encoding {
+        "gzip" => GzDecoder::new(body).read_to_string(&mut decoded)?,
+        "deflate" => ZlibDecoder::new(body).read_to_string(&mut decoded)?,
+        "br" => brotli::Decompressor::new(body, 4096).read_to_string(&mut decoded)?,
+        "zstd" => zstd::Decoder::new(body)?.read_to_string(&mut decoded)?,
+        other => panic!("Unexpected encoding {}", other),
+    };
+    Ok(decoded)
+}
+
+/// The encoding is picked based on Accept-Encoding, preferring the client's favorite.
+#[rstest(
+    accept_encoding,
+    expected,
+    case::gzip("gzip", "gzip"),
+    case::deflate("deflate", "deflate"),
+    case::br("br", "br"),
+    case::zstd("zstd", "zstd"),
+    case::quality("gzip;q=0.5, deflate;q=0.8", "deflate"),
+    case::tie("gzip, br", "br"),
+    case::wildcard("gzip;q=0.1, *", "zstd"),
+    case::excluded("zstd;q=0, br;q=0, *;q=0.5", "gzip")
+)]
+fn negotiates_encoding(accept_encoding: &str, expected: &str) -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--compression", "auto"])?;
+
+    let resp = Client::new()
+        .get(&dh.url)
+        .header("Accept-Encoding", accept_encoding)
+        .send()?;
+    assert_eq!(resp.headers()["content-encoding"], expected);
+    assert_eq!(resp.headers()["vary"], "accept-encoding");
+    assert_eq!(decode(expected, &resp.bytes()?)?, "dummyhttp");
+
+    Ok(())
+}
+
+/// Bodies are sent as they are if the client doesn't accept any supported encoding.
+#[rstest(
+    accept_encoding,
+    case::missing(None),
+    case::identity(Some("identity")),
+    case::unsupported(Some("compress")),
+    case::refused(Some("*;q=0"))
+)]
+fn sends_identity_if_nothing_accepted(accept_encoding: Option<&str>) -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--compression", "auto"])?;
+
+    let mut req = Client::new().get(&dh.url);
+    if let Some(accept_encoding) = accept_encoding {
+        req = req.header("Accept-Encoding", accept_encoding);
+    }
+    let resp = req.send()?;
+    assert!(resp.headers().get("content-encoding").is_none());
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
+
+/// Bodies aren't compressed unless asked for.
+#[test]
+fn off_by_default() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q"])?;
+
+    let resp = Client::new()
+        .get(&dh.url)
+        .header("Accept-Encoding", "gzip, br")
+        .send()?;
+    assert!(resp.headers().get("content-encoding").is_none());
+    assert!(resp.headers().get("vary").is_none());
+    assert_eq!(resp.headers()["content-length"], "9");
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
+
+/// --compression forces an encoding or turns compression off.
+#[rstest(
+    compression,
+    accept_encoding,
+    expected,
+    case::forced("br", "gzip", Some("br")),
+    case::forced_without_accept_encoding("zstd", "", Some("zstd")),
+    case::off("off", "gzip, br", None)
+)]
+fn compression_setting(
+    compression: &'static str,
+    accept_encoding: &str,
+    expected: Option<&str>,
+) -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--compression", compression])?;
+
+    let resp = Client::new()
+        .get(&dh.url)
+        .header("Accept-Encoding", accept_encoding)
+        .send()?;
+    let encoding = resp
+        .headers()
+        .get("content-encoding")
+        .map(|encoding| encoding.to_str().unwrap().to_string());
+    assert_eq!(encoding.as_deref(), expected);
+    let body = resp.bytes()?;
+    match expected {
+        Some(expected) => assert_eq!(decode(expected, &body)?, "dummyhttp"),
+        None => assert_eq!(body, "dummyhttp"),
+    }
+
+    Ok(())
+}
+
+/// --fake-content-encoding announces an encoding that wasn't used.
+#[test]
+fn fakes_content_encoding() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "-q",
+        "--compression",
+        "off",
+        "--fake-content-encoding",
+        "gzip",
+    ])?;
+
+    let resp = Client::new()
+        .get(&dh.url)
+        .header("Accept-Encoding", "gzip")
+        .send()?;
+    assert_eq!(resp.headers()["content-encoding"], "gzip");
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
+
+/// Compressed request bodies are logged decompressed.
+#[test]
+fn logs_decompressed_request_body() -> Result<(), Error> {
+    let mut dh = DummyhttpProcess::new(vec!["-vv", "--log-format", "json"])?;
+
+    let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(b"compressed request")?;
+    Client::new()
+        .post(&dh.url)
+        .header("Content-Encoding", "gzip")
+        .body(encoder.finish()?)
+        .send()?;
+
+    dh.child.kill()?;
+    let mut output = String::new();
+    dh.child
+        .stdout
+        .as_mut()
+        .unwrap()
+        .read_to_string(&mut output)?;
+    let record = output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|record| record.get("method").is_some())
+        .unwrap();
+    assert_eq!(record["request_body"], "compressed request");
+
+    Ok(())
+}
+
+/// Decompressed request bodies are cut off after 1 MiB when logged.
+#[test]
+fn truncates_large_decompressed_request_body() -> Result<(), Error> {
+    let mut dh = DummyhttpProcess::new(vec!["-vv", "--log-format", "json"])?;
+
+    // The log line is larger than the pipe buffer, so it has to be read while it's written.
+    let mut stdout = dh.child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+
+    let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(&vec![b'a'; 16 * 1024 * 1024])?;
+    let resp = Client::new()
+        .post(&dh.url)
+        .header("Content-Encoding", "gzip")
+        .body(encoder.finish()?)
+        .send()?;
+    assert!(resp.status().is_success());
+
+    dh.child.kill()?;
+    let output = reader.join().unwrap()?;
+    let record = output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|record| record.get("method").is_some())
+        .unwrap();
+    let body = record["request_body"].as_str().unwrap();
+    assert_eq!(body.len(), 1024 * 1024 + "... (truncated)".len());
+    assert!(body.ends_with("a... (truncated)"));
+
+    Ok(())
+}
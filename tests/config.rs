@@ -0,0 +1,107 @@
+mod utils;
+
+use assert_cmd::prelude::*;
+use axum::http::StatusCode;
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use std::process::Command;
+use utils::{DummyhttpProcess, Error};
+
+/// Routes from a config file are matched by method and path.
+#[rstest(
+    config,
+    case::yaml("tests/data/routes.yaml"),
+    case::toml("tests/data/routes.toml"),
+    case::json("tests/data/routes.json")
+)]
+fn serves_configured_routes(config: &'static str) -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--config", config])?;
+
+    let client = Client::new();
+
+    let resp = client.post(format!("{}/jobs", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    assert_eq!(resp.text()?, r#"{"status": "accepted"}"#);
+
+    let resp = client.get(format!("{}/jobs/1", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "pending");
+
+    let resp = client
+        .get(format!("{}/static/some/file.txt", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.text()?, "not here");
+
+    Ok(())
+}
+
+/// Requests not matching any route get the response given on the command line.
+#[test]
+fn unmatched_requests_get_default_response() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "--config",
+        "tests/data/routes.yaml",
+        "-c",
+        "418",
+        "-b",
+        "fallback",
+    ])?;
+
+    let client = Client::new();
+
+    // Wrong method for an existing path.
+    let resp = client.delete(format!("{}/jobs/1", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::IM_A_TEAPOT);
+    assert_eq!(resp.text()?, "fallback");
+
+    // Path with too many segments.
+    let resp = client.get(format!("{}/jobs/1/extra", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::IM_A_TEAPOT);
+    assert_eq!(resp.text()?, "fallback");
+
+    Ok(())
+}
+
+/// Invalid config files are rejected on startup.
+#[test]
+fn invalid_config_fails() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--config", "tests/data/does-not-exist.yaml"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Error: Failed to read config file 'tests/data/does-not-exist.yaml'",
+        ));
+
+    Command::cargo_bin("dummyhttp")?
+        .args(["--config", "tests/data/cert.pem"])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown config file format"));
+
+    Ok(())
+}
+
+/// Misspelled fields are rejected instead of being ignored.
+#[test]
+fn unknown_field_fails() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--config", "tests/data/unknown_field.yaml"])
+        .assert()
+        .failure()
+        .stderr(contains("unknown field `boddy` in route #2"));
+
+    Command::cargo_bin("dummyhttp")?
+        .args(["--config", "tests/data/unknown_default_field.yaml"])
+        .assert()
+        .failure()
+        .stderr(contains("unknown field `dealy`"));
+
+    Ok(())
+}
@@ -1,2 +1,9 @@
 #!/usr/bin/env bash
 openssl req -subj '/CN=localhost' -x509 -newkey rsa:4096 -keyout key.pem -out cert.pem -nodes -days 3650
+
+# CA and client certificate for mutual TLS
+openssl req -subj '/CN=dummyhttp test CA' -x509 -newkey rsa:2048 -keyout client_ca_key.pem -out client_ca.pem -nodes -days 3650
+openssl req -subj '/CN=client/O=dummyhttp' -newkey rsa:2048 -keyout client_key.pem -out client.csr -nodes
+openssl x509 -req -in client.csr -CA client_ca.pem -CAkey client_ca_key.pem -CAcreateserial -out client_cert.pem -days 3650 \
+    -extfile <(printf "subjectAltName=DNS:client.dummyhttp.test,IP:127.0.0.1\nextendedKeyUsage=clientAuth")
+rm client.csr client_ca_key.pem client_ca.srl
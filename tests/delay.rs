@@ -0,0 +1,182 @@
+mod utils;
+
+use assert_cmd::prelude::*;
+use axum::http::StatusCode;
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use utils::{DummyhttpProcess, Error};
+
+/// Responses are delayed according to --delay.
+#[rstest(
+    delay,
+    min,
+    case::fixed("300ms", 300),
+    case::uniform("200ms..400ms", 200),
+    case::normal("normal:300ms,0ms", 300),
+    case::lognormal("lognormal:300ms,1ms", 250)
+)]
+fn delays_response(delay: &'static str, min: u64) -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--delay", delay])?;
+
+    let start = Instant::now();
+    let resp = Client::new().get(&dh.url).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "dummyhttp");
+    assert!(start.elapsed() >= Duration::from_millis(min));
+
+    Ok(())
+}
+
+/// With --trickle, the body is spread over the given duration.
+#[test]
+fn trickles_body() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--trickle", "500ms", "-b", "slow body"])?;
+
+    let start = Instant::now();
+    let resp = Client::new().get(&dh.url).send()?;
+    let headers_received = start.elapsed();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("content-length").unwrap(), "9");
+    assert_eq!(resp.text()?, "slow body");
+    assert!(headers_received < Duration::from_millis(500));
+    assert!(start.elapsed() >= Duration::from_millis(500));
+
+    Ok(())
+}
+
+/// With --chunk-size, the body is sent with chunked transfer encoding in chunks of that size.
+#[test]
+fn sends_body_in_chunks() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "-q",
+        "--chunk-size",
+        "4",
+        "--chunk-delay",
+        "100ms",
+        "-b",
+        "slow body",
+    ])?;
+
+    // Read the response by hand as clients hide the chunk boundaries.
+    let start = Instant::now();
+    let mut stream = TcpStream::connect(format!("localhost:{}", dh.port))?;
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp)?;
+    assert!(resp.contains("transfer-encoding: chunked\r\n"));
+    assert!(!resp.contains("content-length"));
+    assert!(resp.ends_with("\r\n\r\n4\r\nslow\r\n4\r\n bod\r\n1\r\ny\r\n0\r\n\r\n"));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    Ok(())
+}
+
+/// With --bandwidth, the body is sent no faster than the given rate.
+#[test]
+fn limits_bandwidth() -> Result<(), Error> {
+    let body = "x".repeat(100);
+    let dh = DummyhttpProcess::new(vec![
+        "--bandwidth".to_string(),
+        "200".to_string(),
+        "-b".to_string(),
+        body.clone(),
+    ])?;
+
+    let start = Instant::now();
+    let resp = Client::new().get(&dh.url).send()?;
+    assert_eq!(resp.headers().get("content-length").unwrap(), "100");
+    assert_eq!(resp.text()?, body);
+    // The last chunk of 2 bytes is sent after 98 bytes took 490ms.
+    assert!(start.elapsed() >= Duration::from_millis(490));
+
+    Ok(())
+}
+
+/// Routes can stream their bodies as well.
+#[test]
+fn streams_configured_routes() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--config", "tests/data/streaming.yaml"])?;
+
+    let resp = Client::new().get(format!("{}/chunked", dh.url)).send()?;
+    assert_eq!(resp.headers().get("transfer-encoding").unwrap(), "chunked");
+    assert_eq!(resp.text()?, "chunked body");
+
+    let resp = Client::new().get(format!("{}/throttled", dh.url)).send()?;
+    assert_eq!(resp.headers().get("content-length").unwrap(), "14");
+    assert_eq!(resp.text()?, "throttled body");
+
+    Ok(())
+}
+
+/// Only one way of streaming the body can be picked.
+#[rstest(
+    args,
+    case::trickle_and_chunks(&["--trickle", "1s", "--chunk-size", "4"]),
+    case::bandwidth_and_chunk_delay(&["--bandwidth", "1k", "--chunk-delay", "10ms"]),
+    case::trickle_and_bandwidth(&["--trickle", "1s", "--bandwidth", "1k"])
+)]
+fn conflicting_streaming_modes_fail(args: &[&str]) -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(args)
+        .assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+
+    Ok(())
+}
+
+/// Routes are checked for conflicting ways of streaming on startup.
+#[test]
+fn conflicting_streaming_modes_in_config_fail() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--config", "tests/data/invalid_streaming.yaml"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Only one of trickle, chunk_size/chunk_delay and bandwidth can be given",
+        ));
+
+    Ok(())
+}
+
+/// Invalid bandwidths are rejected.
+#[rstest(
+    bandwidth,
+    case::zero("0"),
+    case::unknown_unit("10g"),
+    case::not_a_number("fast"),
+    case::too_large("18014398509481984m")
+)]
+fn invalid_bandwidth_fails(bandwidth: &'static str) -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--bandwidth", bandwidth])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid value"));
+
+    Ok(())
+}
+
+/// Invalid delays are rejected.
+#[rstest(
+    delay,
+    case::no_unit("300"),
+    case::unknown_unit("300h"),
+    case::reversed_range("400ms..200ms"),
+    case::missing_std_dev("normal:300ms"),
+    case::too_long("100000000000000000000000000000s")
+)]
+fn invalid_delay_fails(delay: &'static str) -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--delay", delay])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid value"));
+
+    Ok(())
+}
@@ -0,0 +1,227 @@
+#![cfg(unix)]
+
+mod utils;
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use assert_cmd::cargo::cargo_bin;
+use assert_cmd::prelude::*;
+use port_check::{free_local_port, is_port_reachable};
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use socket2::{Domain, Socket, Type};
+use utils::{inherit_fd, Error};
+
+/// Kills the wrapped dummyhttp once dropped
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+/// Start dummyhttp with `listener` available as file descriptor 3
+fn spawn(listener: &impl AsRawFd, args: &[&str]) -> Result<KillOnDrop, Error> {
+    let mut command = Command::cargo_bin("dummyhttp")?;
+    command.args(args).stdout(Stdio::piped());
+    inherit_fd(&mut command, listener, 3);
+    Ok(KillOnDrop(command.spawn()?))
+}
+
+/// The first line dummyhttp prints
+fn first_line(dh: &mut KillOnDrop) -> Result<String, Error> {
+    dh.0.kill()?;
+    let mut output = String::new();
+    dh.0.stdout.as_mut().unwrap().read_to_string(&mut output)?;
+    Ok(output.lines().next().unwrap_or_default().to_string())
+}
+
+/// A socket bound by the parent accepts requests without waiting for dummyhttp to start.
+#[test]
+fn serves_on_inherited_socket() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let mut dh = spawn(&listener, &["--fd", "3", "-b", "inherited"])?;
+    drop(listener);
+
+    let resp = reqwest::blocking::get(format!("http://{addr}"))?;
+    assert_eq!(resp.text()?, "inherited");
+    assert!(first_line(&mut dh)?.contains(&format!("http://{addr}")));
+
+    Ok(())
+}
+
+/// Inherited sockets speak HTTPS if a certificate is given.
+#[test]
+#[cfg(feature = "tls")]
+fn serves_https_on_inherited_socket() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let _dh = spawn(&listener, &["--fd", "3", "--tls-self-signed"])?;
+
+    let resp = Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()?
+        .get(format!("https://{addr}"))
+        .send()?;
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
+
+/// Sockets which are bound but not listening yet are put into listening state.
+#[test]
+fn listens_on_bound_socket() -> Result<(), Error> {
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, None)?;
+    socket.bind(&"127.0.0.1:0".parse::<SocketAddr>()?.into())?;
+    let addr = socket.local_addr()?.as_socket().unwrap();
+    let mut dh = spawn(&socket, &["--listen", "fd:3"])?;
+
+    // Give dummyhttp a moment to call listen(2).
+    let client = Client::new();
+    let start = Instant::now();
+    let resp = loop {
+        match client.get(format!("http://{addr}")).send() {
+            Ok(resp) => break resp,
+            Err(_) if start.elapsed().as_secs() < 1 => sleep(Duration::from_millis(50)),
+            Err(err) => return Err(err.into()),
+        }
+    };
+    assert_eq!(resp.text()?, "dummyhttp");
+    assert!(first_line(&mut dh)?.contains(&format!("http://{addr}")));
+
+    Ok(())
+}
+
+/// Inherited Unix sockets are logged with their path.
+#[test]
+fn serves_on_inherited_unix_socket() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("dummyhttp-fd-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    let mut dh = spawn(&listener, &["--fd", "3", "-b", "over uds"])?;
+
+    let mut stream = UnixStream::connect(&path)?;
+    write!(
+        stream,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    assert!(response.ends_with("over uds"));
+    assert!(first_line(&mut dh)?.contains(&format!("http://unix:{}", path.display())));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+/// Inherited Unix sockets can't speak HTTPS.
+#[test]
+#[cfg(feature = "tls")]
+fn rejects_tls_on_inherited_unix_socket() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("dummyhttp-fd-tls-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    let mut command = Command::cargo_bin("dummyhttp")?;
+    command.args(["--fd", "3", "--tls-self-signed"]);
+    inherit_fd(&mut command, &listener, 3);
+    command.assert().failure().stderr(contains(
+        "TLS is not supported on Unix sockets, but file descriptor 3 is one",
+    ));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+/// File descriptors given more than once are only served once.
+#[test]
+fn adopts_repeated_fd_once() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let mut dh = spawn(&listener, &["--fd", "3", "--fd", "3", "--listen", "fd:3"])?;
+    drop(listener);
+
+    let client = Client::new();
+    for _ in 0..3 {
+        let resp = client.get(format!("http://{addr}")).send()?;
+        assert_eq!(resp.text()?, "dummyhttp");
+    }
+    dh.0.kill()?;
+    let mut output = String::new();
+    dh.0.stdout.as_mut().unwrap().read_to_string(&mut output)?;
+    assert_eq!(output.matches(&format!("http://{addr}")).count(), 1);
+
+    Ok(())
+}
+
+/// Sockets passed via systemd socket activation are used if no listener is given.
+#[test]
+fn uses_systemd_sockets() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(r#"LISTEN_PID=$$ exec "$0" -b activated"#)
+        .arg(cargo_bin("dummyhttp"))
+        .env("LISTEN_FDS", "1")
+        .stdout(Stdio::null());
+    inherit_fd(&mut command, &listener, 3);
+    let _dh = KillOnDrop(command.spawn()?);
+
+    let resp = reqwest::blocking::get(format!("http://{addr}"))?;
+    assert_eq!(resp.text()?, "activated");
+
+    Ok(())
+}
+
+/// Sockets meant for another process are ignored.
+#[test]
+fn ignores_systemd_sockets_of_other_processes() -> Result<(), Error> {
+    let port = free_local_port().unwrap();
+    let _dh = KillOnDrop(
+        Command::cargo_bin("dummyhttp")?
+            .args(["-p", &port.to_string()])
+            .env("LISTEN_PID", "1")
+            .env("LISTEN_FDS", "1")
+            .stdout(Stdio::null())
+            .spawn()?,
+    );
+
+    let start = Instant::now();
+    while start.elapsed().as_secs() < 1 && !is_port_reachable(format!("localhost:{port}")) {
+        sleep(Duration::from_millis(100));
+    }
+    assert!(is_port_reachable(format!("localhost:{port}")));
+
+    Ok(())
+}
+
+/// File descriptors which aren't sockets are rejected.
+#[test]
+fn rejects_non_socket_fd() -> Result<(), Error> {
+    let file = File::open("Cargo.toml")?;
+    let mut command = Command::cargo_bin("dummyhttp")?;
+    command.args(["--fd", "3"]);
+    inherit_fd(&mut command, &file, 3);
+    command
+        .assert()
+        .failure()
+        .stderr(contains("Can't listen on file descriptor 3"));
+
+    Command::cargo_bin("dummyhttp")?
+        .args(["--listen", "fd:nope"])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid listen address 'fd:nope'"));
+
+    Ok(())
+}
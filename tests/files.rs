@@ -0,0 +1,107 @@
+mod utils;
+
+use assert_cmd::prelude::*;
+use axum::http::StatusCode;
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+use utils::{DummyhttpProcess, Error};
+
+/// --body-file sends the file as is and guesses its Content-Type.
+#[test]
+fn serves_body_file() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--body-file", "tests/data/files/template.json"])?;
+
+    let resp = Client::new().get(format!("{}?id=1", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    assert_eq!(resp.text()?, "{\"id\": \"{{ request.query.id }}\"}\n");
+
+    Ok(())
+}
+
+/// --render-body-file renders the file as a template.
+#[test]
+fn serves_rendered_body_file() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "--body-file",
+        "tests/data/files/template.json",
+        "--render-body-file",
+        "-H",
+        "content-type:text/plain",
+    ])?;
+
+    let resp = Client::new().get(format!("{}?id=1", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "text/plain");
+    assert_eq!(resp.text()?, "{\"id\": \"1\"}\n");
+
+    Ok(())
+}
+
+/// Body files in the config are relative to the config file.
+#[test]
+fn serves_body_files_from_config() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--config", "tests/data/body_files.yaml"])?;
+
+    let client = Client::new();
+    let resp = client.get(format!("{}/raw?id=1", dh.url)).send()?;
+    assert_eq!(resp.text()?, "{\"id\": \"{{ request.query.id }}\"}\n");
+    let resp = client.get(format!("{}/rendered?id=1", dh.url)).send()?;
+    assert_eq!(resp.text()?, "{\"id\": \"1\"}\n");
+
+    Ok(())
+}
+
+/// --serve-dir maps request paths to files and falls back to the default response.
+#[test]
+fn serves_directory() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--serve-dir", "tests/data/files"])?;
+
+    let client = Client::new();
+
+    let resp = client.get(format!("{}/template.json", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    assert_eq!(resp.text()?, "{\"id\": \"{{ request.query.id }}\"}\n");
+
+    let resp = client.get(format!("{}/nested/", dh.url)).send()?;
+    assert_eq!(resp.headers().get("content-type").unwrap(), "text/html");
+    assert_eq!(resp.text()?, "<h1>index</h1>\n");
+
+    let resp = client.get(format!("{}/missing.txt", dh.url)).send()?;
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    // Send the request by hand as clients normalize `..` away.
+    let mut stream = TcpStream::connect(format!("localhost:{}", dh.port))?;
+    stream.write_all(
+        b"GET /nested/../../body_files.yaml HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    )?;
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp)?;
+    assert!(resp.ends_with("\r\n\r\ndummyhttp"));
+
+    Ok(())
+}
+
+/// Missing body files are reported on startup.
+#[test]
+fn missing_body_file_fails() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--body-file", "tests/data/missing.json"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Error: Failed to read body file 'tests/data/missing.json'",
+        ));
+
+    Ok(())
+}
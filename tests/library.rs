@@ -0,0 +1,98 @@
+use std::net::TcpStream;
+
+use axum::http::StatusCode;
+use dummyhttp::{DummyServer, RouteConfig};
+use reqwest::blocking::Client;
+
+type Error = Box<dyn std::error::Error>;
+
+/// Without any configuration, the server answers like dummyhttp does by default.
+#[test]
+fn serves_default_response() -> Result<(), Error> {
+    let server = DummyServer::builder().arg("-q").start()?;
+
+    let resp = reqwest::blocking::get(server.url())?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "dummyhttp");
+    assert!(server.addr().ip().is_loopback());
+
+    Ok(())
+}
+
+/// Routes and the default response can be configured and received requests are recorded.
+#[test]
+fn serves_configured_routes() -> Result<(), Error> {
+    let server = DummyServer::builder()
+        .arg("-q")
+        .code(404)
+        .header("x-dummy", "yes")
+        .body("not found")
+        .route(
+            RouteConfig::new("/users/:id")
+                .method("GET")
+                .query("expand", "true")
+                .code(200)
+                .header("content-type", "text/plain")
+                .body("user {{ request.params.id }}"),
+        )
+        .start()?;
+
+    let client = Client::new();
+    let resp = client
+        .get(format!("{}/users/7?expand=true", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "user 7");
+
+    let resp = client.get(format!("{}/users/7", server.url())).send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.headers()["x-dummy"], "yes");
+    assert_eq!(resp.text()?, "not found");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].query.as_deref(), Some("expand=true"));
+    assert_eq!(requests[1].query, None);
+    assert_eq!(requests[1].status, 404);
+
+    server.clear_requests();
+    assert!(server.requests().is_empty());
+
+    Ok(())
+}
+
+/// The server stops listening once dropped.
+#[test]
+fn shuts_down_on_drop() -> Result<(), Error> {
+    let server = DummyServer::builder().arg("-q").start()?;
+    let addr = server.addr();
+    assert!(TcpStream::connect(addr).is_ok());
+
+    drop(server);
+    assert!(TcpStream::connect(addr).is_err());
+
+    Ok(())
+}
+
+/// Stopping the server reports whether it kept serving until then.
+#[test]
+fn stops_without_error() -> Result<(), Error> {
+    let server = DummyServer::builder().arg("-q").start()?;
+    let addr = server.addr();
+
+    server.stop()?;
+    assert!(TcpStream::connect(addr).is_err());
+
+    Ok(())
+}
+
+/// Invalid arguments are reported when starting.
+#[test]
+fn rejects_invalid_arguments() {
+    let err = DummyServer::builder()
+        .arg("--delay")
+        .arg("soon")
+        .start()
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("Invalid arguments"));
+}
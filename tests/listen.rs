@@ -0,0 +1,151 @@
+mod utils;
+
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use assert_cmd::prelude::*;
+use port_check::{free_local_port, is_port_reachable};
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use utils::Error;
+
+/// Kills the wrapped dummyhttp once dropped
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+/// Start dummyhttp with the given args and wait for all `ports` to accept connections
+fn spawn(args: &[String], ports: &[u16]) -> Result<KillOnDrop, Error> {
+    let child = Command::cargo_bin("dummyhttp")?
+        .args(args)
+        .stdout(Stdio::null())
+        .spawn()?;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1)
+        && !ports
+            .iter()
+            .all(|port| is_port_reachable(format!("localhost:{port}")))
+    {
+        sleep(Duration::from_millis(100));
+    }
+    Ok(KillOnDrop(child))
+}
+
+fn client() -> Result<Client, Error> {
+    Ok(Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()?)
+}
+
+/// One process can serve plain HTTP and HTTPS at the same time.
+#[test]
+#[cfg(feature = "tls")]
+fn serves_http_and_https() -> Result<(), Error> {
+    let (http_port, https_port) = (free_local_port().unwrap(), free_local_port().unwrap());
+    let _dh = spawn(
+        &[
+            "--tls-self-signed".to_string(),
+            "--listen".to_string(),
+            format!("127.0.0.1:{http_port}"),
+            "--listen".to_string(),
+            format!("127.0.0.1:{https_port}+tls"),
+        ],
+        &[http_port, https_port],
+    )?;
+
+    let resp = client()?
+        .get(format!("http://127.0.0.1:{http_port}"))
+        .send()?;
+    assert_eq!(resp.text()?, "dummyhttp");
+    let resp = client()?
+        .get(format!("https://127.0.0.1:{https_port}"))
+        .send()?;
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
+
+/// The same port can be used on IPv4 and IPv6 addresses.
+#[test]
+fn serves_ipv4_and_ipv6_on_same_port() -> Result<(), Error> {
+    let port = free_local_port().unwrap();
+    let _dh = spawn(
+        &[
+            "--listen".to_string(),
+            format!("127.0.0.1:{port}"),
+            "--listen".to_string(),
+            format!("[::1]:{port}"),
+        ],
+        &[port],
+    )?;
+
+    for host in ["127.0.0.1", "[::1]"] {
+        let resp = client()?.get(format!("http://{host}:{port}")).send()?;
+        assert_eq!(resp.text()?, "dummyhttp");
+    }
+
+    Ok(())
+}
+
+/// Listeners can serve their own route configuration.
+#[test]
+fn listeners_serve_their_own_routes() -> Result<(), Error> {
+    let (default_port, routes_port) = (free_local_port().unwrap(), free_local_port().unwrap());
+    let _dh = spawn(
+        &[
+            "-b".to_string(),
+            "default".to_string(),
+            "--listen".to_string(),
+            format!("127.0.0.1:{default_port}"),
+            "--listen".to_string(),
+            format!("127.0.0.1:{routes_port},config=tests/data/routes.yaml"),
+        ],
+        &[default_port, routes_port],
+    )?;
+
+    let resp = client()?
+        .get(format!("http://127.0.0.1:{default_port}/jobs/1"))
+        .send()?;
+    assert_eq!(resp.text()?, "default");
+    let resp = client()?
+        .get(format!("http://127.0.0.1:{routes_port}/jobs/1"))
+        .send()?;
+    assert_eq!(resp.text()?, "pending");
+
+    Ok(())
+}
+
+/// TLS listeners need a certificate.
+#[test]
+fn tls_listener_without_certificate_fails() -> Result<(), Error> {
+    let port = free_local_port().unwrap();
+    Command::cargo_bin("dummyhttp")?
+        .args(["--listen", &format!("127.0.0.1:{port}+tls")])
+        .assert()
+        .failure()
+        .stderr(contains(format!("Listener 127.0.0.1:{port} uses TLS")));
+
+    Ok(())
+}
+
+/// Invalid listen addresses are rejected.
+#[test]
+fn invalid_listen_fails() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--listen", "localhost"])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid listen address 'localhost'"));
+    Command::cargo_bin("dummyhttp")?
+        .args(["--listen", "127.0.0.1:0,nope"])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown listen option 'nope'"));
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+mod utils;
+
+use chrono::Local;
+use reqwest::blocking::Client;
+use serde_json::Value;
+use std::io::Read;
+use utils::{DummyhttpProcess, Error};
+
+fn output_after_request(args: Vec<&'static str>) -> Result<String, Error> {
+    let mut dh = DummyhttpProcess::new(args)?;
+
+    Client::new()
+        .post(format!("{}/jobs?a=b", dh.url))
+        .header("x-request-id", "1")
+        .body("some body")
+        .send()?
+        .error_for_status()?;
+
+    dh.child.kill()?;
+    let mut output = String::new();
+    dh.child
+        .stdout
+        .as_mut()
+        .unwrap()
+        .read_to_string(&mut output)?;
+    Ok(output)
+}
+
+/// With --log-format json, every line is a JSON object.
+#[test]
+fn logs_json() -> Result<(), Error> {
+    let output = output_after_request(vec!["--log-format", "json", "-b", "teststring"])?;
+
+    let lines = output
+        .lines()
+        .map(serde_json::from_str::<Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("listening on"));
+
+    let record = &lines[1];
+    assert_eq!(record["method"], "POST");
+    assert_eq!(record["uri"], "/jobs?a=b");
+    assert_eq!(record["version"], "1.1");
+    assert_eq!(record["status"], 200);
+    assert_eq!(record["request_headers"]["x-request-id"], "1");
+    assert_eq!(record["response_headers"]["content-length"], "10");
+    assert!(record.get("request_body").is_none());
+    assert!(record.get("response_body").is_none());
+    assert!(!output.contains('\u{1b}'));
+
+    Ok(())
+}
+
+/// With -vv, the bodies are part of the record.
+#[test]
+fn logs_json_bodies_when_very_verbose() -> Result<(), Error> {
+    let output = output_after_request(vec!["--log-format", "json", "-vv", "-b", "teststring"])?;
+
+    let record: Value = serde_json::from_str(output.lines().nth(1).unwrap())?;
+    assert_eq!(record["request_body"], "some body");
+    assert_eq!(record["response_body"], "teststring");
+
+    Ok(())
+}
+
+/// With --log-format logfmt, every request is a line of key=value pairs.
+#[test]
+fn logs_logfmt() -> Result<(), Error> {
+    let output = output_after_request(vec!["--log-format", "logfmt", "-vv", "-b", "teststring"])?;
+
+    let line = output.lines().nth(1).unwrap();
+    assert!(line.starts_with("timestamp="));
+    assert!(line.contains(r#" method=POST uri="/jobs?a=b" version=1.1 status=200 "#));
+    assert!(line.contains(" request_header.x-request-id=1 "));
+    assert!(line.contains(r#" request_body="some body" "#));
+    assert!(line.ends_with(" response_body=teststring"));
+
+    Ok(())
+}
+
+/// The pretty log shows the date with the month in the right place.
+#[test]
+fn logs_pretty_date() -> Result<(), Error> {
+    let output = output_after_request(vec![])?;
+
+    assert!(output.contains(&Local::now().format("%Y-%m-%d").to_string()));
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+mod utils;
+
+use std::io::Read;
+
+use assert_cmd::prelude::*;
+use axum::http::StatusCode;
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use std::process::Command;
+use utils::{DummyhttpProcess, Error};
+
+/// Paths can be matched exactly, by prefix, regex or glob.
+#[rstest(
+    path,
+    expected,
+    case::exact("/exact", "exact"),
+    case::exact_mismatch("/exact/more", "no match"),
+    case::prefix("/api/v1/things", "prefix"),
+    case::regex("/users/42", "user 42"),
+    case::regex_mismatch("/users/abc", "no match"),
+    case::glob("/assets/js/vendor/app.js", "glob"),
+    case::glob_mismatch("/assets/app.css", "no match")
+)]
+fn matches_paths(path: &str, expected: &str) -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--config", "tests/data/matching.yaml"])?;
+
+    let resp = Client::new().get(format!("{}{}", dh.url, path)).send()?;
+    assert_eq!(resp.text()?, expected);
+
+    Ok(())
+}
+
+/// Query parameters and headers can be required to equal, match or be present.
+#[test]
+fn matches_query_and_headers() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--config", "tests/data/matching.yaml"])?;
+    let client = Client::new();
+
+    let resp = client
+        .get(format!("{}/search?q=rust&page=2", dh.url))
+        .send()?;
+    assert_eq!(resp.text()?, "search");
+    let resp = client
+        .get(format!("{}/search?q=rust&page=two", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp = client
+        .get(format!("{}/search?q=rust&page=2&debug=1", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = client
+        .get(format!("{}/secret", dh.url))
+        .header("authorization", "Bearer token")
+        .header("x-api-key", "key")
+        .send()?;
+    assert_eq!(resp.text()?, "secret");
+    let resp = client
+        .get(format!("{}/secret", dh.url))
+        .header("authorization", "Bearer token")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+/// Bodies can be matched by substring, regex, JSONPath and partial JSON.
+#[rstest(
+    body,
+    expected,
+    case::json(
+        r#"{"items": [{"sku": "ABC"}], "customer": {"vip": true, "name": "x"}}"#,
+        "vip order"
+    ),
+    case::json_not_vip(
+        r#"{"items": [{"sku": "ABC"}], "customer": {"vip": false}}"#,
+        "no match"
+    ),
+    case::contains("this is urgent!", "urgent order"),
+    case::regex("id=12", "form order"),
+    case::nothing("something else", "no match")
+)]
+fn matches_bodies(body: &'static str, expected: &str) -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--config", "tests/data/matching.yaml"])?;
+
+    let resp = Client::new()
+        .post(format!("{}/orders", dh.url))
+        .body(body)
+        .send()?;
+    assert_eq!(resp.text()?, expected);
+
+    Ok(())
+}
+
+/// Why a request didn't match any route is logged.
+#[test]
+fn logs_unmatched_reason() -> Result<(), Error> {
+    let mut dh = DummyhttpProcess::new(vec!["--config", "tests/data/matching.yaml"])?;
+
+    Client::new()
+        .get(format!("{}/secret", dh.url))
+        .header("authorization", "Basic abc")
+        .send()?;
+    Client::new().get(format!("{}/nowhere", dh.url)).send()?;
+
+    dh.child.kill()?;
+    let mut output = String::new();
+    dh.child
+        .stdout
+        .as_mut()
+        .unwrap()
+        .read_to_string(&mut output)?;
+
+    assert!(output.contains("route #6 (/secret) header 'authorization' does not match '^Bearer '"));
+    assert!(output.contains("no route for path /nowhere"));
+
+    Ok(())
+}
+
+/// Invalid matchers are reported on startup.
+#[test]
+fn rejects_invalid_matchers() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--config", "tests/data/invalid_matcher.yaml"])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid path regex"));
+
+    Ok(())
+}
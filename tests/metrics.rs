@@ -0,0 +1,86 @@
+mod utils;
+
+use axum::http::StatusCode;
+use port_check::free_local_port;
+use reqwest::blocking::Client;
+use utils::{DummyhttpProcess, Error};
+
+/// Requests are counted by method, route and status and shown at /__dummyhttp/metrics.
+#[test]
+fn counts_requests() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-c", "418", "--delay", "10ms"])?;
+
+    let client = Client::new();
+    client
+        .post(format!("{}/jobs", dh.url))
+        .body("12345")
+        .send()?;
+    client.post(format!("{}/jobs", dh.url)).send()?;
+    client.get(format!("{}/other", dh.url)).send()?;
+    client
+        .request(reqwest::Method::from_bytes(b"FOO123")?, &dh.url)
+        .send()?;
+
+    let resp = client
+        .get(format!("{}/__dummyhttp/metrics", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let metrics = resp.text()?;
+    assert!(metrics
+        .contains(r#"dummyhttp_requests_total{method="POST",route="default",status="418"} 2"#));
+    assert!(metrics
+        .contains(r#"dummyhttp_requests_total{method="GET",route="default",status="418"} 1"#));
+    assert!(metrics
+        .contains(r#"dummyhttp_requests_total{method="other",route="default",status="418"} 1"#));
+    assert!(!metrics.contains("FOO123"));
+    assert!(metrics.contains("dummyhttp_request_body_bytes_sum 5"));
+    assert!(metrics.contains("dummyhttp_injected_delay_seconds_count 4"));
+    assert!(metrics.contains("dummyhttp_active_connections 1"));
+    assert!(!metrics.contains("/__dummyhttp"));
+
+    Ok(())
+}
+
+/// With --metrics-port, metrics are also served at /metrics on that port.
+#[test]
+fn serves_metrics_on_separate_port() -> Result<(), Error> {
+    let metrics_port = free_local_port().unwrap().to_string();
+    let dh = DummyhttpProcess::new(vec!["--metrics-port".to_string(), metrics_port.clone()])?;
+
+    let client = Client::new();
+    client.get(&dh.url).send()?;
+
+    let resp = client
+        .get(format!("http://localhost:{}/metrics", metrics_port))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp
+        .text()?
+        .contains(r#"dummyhttp_requests_total{method="GET",route="default",status="200"} 1"#));
+
+    Ok(())
+}
+
+/// Requests to different paths of the same route share a time series.
+#[test]
+fn labels_by_route() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--config", "tests/data/routes.yaml"])?;
+
+    let client = Client::new();
+    client.get(format!("{}/jobs/1", dh.url)).send()?;
+    client.get(format!("{}/jobs/2", dh.url)).send()?;
+    client.get(format!("{}/other", dh.url)).send()?;
+
+    let metrics = client
+        .get(format!("{}/__dummyhttp/metrics", dh.url))
+        .send()?
+        .text()?;
+    assert!(metrics.contains(
+        r#"dummyhttp_requests_total{method="GET",route="route #2 (/jobs/:id)",status="200"} 2"#
+    ));
+    assert!(metrics
+        .contains(r#"dummyhttp_requests_total{method="GET",route="default",status="200"} 1"#));
+    assert!(!metrics.contains("/jobs/1"));
+
+    Ok(())
+}
@@ -0,0 +1,220 @@
+mod utils;
+
+use assert_cmd::prelude::*;
+use axum::http::StatusCode;
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use std::process::Command;
+use utils::{DummyhttpProcess, Error};
+
+/// Operations are answered with their examples or bodies made up from the schema.
+#[test]
+fn serves_operations() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--openapi", "tests/data/openapi.yaml"])?;
+    let client = Client::new();
+
+    let resp = client.get(format!("{}/pets", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], "application/json");
+    let body: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(body, json!([{"id": 0, "name": "string", "tag": "dog"}]));
+
+    let resp = client.post(format!("{}/pets", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(body, json!({"id": 1, "name": "Rex"}));
+
+    let resp = client.get(format!("{}/pets/mine", dh.url)).send()?;
+    assert_eq!(resp.headers()["content-type"], "text/plain");
+    assert_eq!(resp.text()?, "Rex");
+
+    let resp = client.get(format!("{}/pets/7", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = client.delete(format!("{}/pets/7", dh.url)).send()?;
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
+
+/// Other documented responses can be selected with the Prefer header.
+#[test]
+fn selects_response_by_prefer_header() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--openapi", "tests/data/openapi.yaml"])?;
+    let client = Client::new();
+
+    let resp = client
+        .post(format!("{}/pets", dh.url))
+        .header("prefer", "code=400")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(body, json!({"error": "invalid pet"}));
+
+    let resp = client
+        .get(format!("{}/pets/7", dh.url))
+        .header("prefer", "code=404")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.text()?, "");
+
+    Ok(())
+}
+
+/// With --openapi-validate, invalid requests are answered with 400.
+#[test]
+fn validates_requests() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "--openapi",
+        "tests/data/openapi.yaml",
+        "--openapi-validate",
+    ])?;
+    let client = Client::new();
+
+    let resp = client.get(format!("{}/pets?limit=10", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = client.get(format!("{}/pets?limit=many", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        resp.text()?,
+        "Request validation failed: query parameter 'limit' must be of type integer"
+    );
+
+    let resp = client.get(format!("{}/pets?limit=1000", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = client.get(format!("{}/pets/abc", dh.url)).send()?;
+    assert_eq!(
+        resp.text()?,
+        "Request validation failed: path parameter 'id' must be of type integer"
+    );
+
+    let resp = client.post(format!("{}/pets", dh.url)).send()?;
+    assert_eq!(
+        resp.text()?,
+        "Request validation failed: request body is missing"
+    );
+
+    let resp = client
+        .post(format!("{}/pets", dh.url))
+        .body(r#"{"name": "Rex", "tag": "bird"}"#)
+        .send()?;
+    assert_eq!(
+        resp.text()?,
+        r#"Request validation failed: request body.tag must be one of ["dog","cat"]"#
+    );
+
+    let resp = client
+        .post(format!("{}/pets", dh.url))
+        .body(r#"{"tag": "dog"}"#)
+        .send()?;
+    assert_eq!(
+        resp.text()?,
+        "Request validation failed: request body is missing property 'name'"
+    );
+
+    let resp = client
+        .post(format!("{}/pets", dh.url))
+        .body(r#"{"name": "Rex", "tag": "dog"}"#)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    Ok(())
+}
+
+/// Paths are served below the path of the server URL.
+#[test]
+fn honours_server_path() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--openapi", "tests/data/openapi_servers.yaml"])?;
+    let client = Client::new();
+
+    let resp = client.post(format!("{}/v1/nodes", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let resp = client.post(format!("{}/nodes", dh.url)).send()?;
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    // Path items can have servers of their own.
+    let resp = client.get(format!("{}/health", dh.url)).send()?;
+    assert_eq!(resp.text()?, "ok");
+
+    Ok(())
+}
+
+/// Self-referential schemas don't make validation recurse endlessly.
+#[test]
+fn validates_recursive_schemas() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "--openapi",
+        "tests/data/openapi_servers.yaml",
+        "--openapi-validate",
+    ])?;
+    let client = Client::new();
+
+    let resp = client
+        .post(format!("{}/v1/nodes", dh.url))
+        .body(r#"{"name": "root", "children": [{"name": "leaf"}]}"#)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let resp = client
+        .post(format!("{}/v1/nodes", dh.url))
+        .body(r#"{"children": []}"#)
+        .send()?;
+    assert_eq!(
+        resp.text()?,
+        "Request validation failed: request body is missing property 'name'"
+    );
+
+    Ok(())
+}
+
+/// Parameters that are only part of a path segment are validated as well.
+#[test]
+fn validates_partial_segment_parameters() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "--openapi",
+        "tests/data/openapi_servers.yaml",
+        "--openapi-validate",
+    ])?;
+    let client = Client::new();
+
+    let resp = client.get(format!("{}/v1/files/7.json", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "file");
+
+    let resp = client.get(format!("{}/v1/files/abc.json", dh.url)).send()?;
+    assert_eq!(
+        resp.text()?,
+        "Request validation failed: path parameter 'file-id' must be of type integer"
+    );
+
+    Ok(())
+}
+
+/// Placeholders without a name are rejected.
+#[test]
+fn rejects_unnamed_parameters() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--openapi", "tests/data/openapi_unnamed_param.yaml"])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid path '/files/{}.json'"))
+        .stderr(contains("Placeholders need a name"));
+
+    Ok(())
+}
+
+/// Documents that aren't OpenAPI 3 are rejected.
+#[test]
+fn rejects_invalid_documents() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--openapi", "tests/data/routes.yaml"])
+        .assert()
+        .failure()
+        .stderr(contains("is not an OpenAPI 3 document"));
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+mod utils;
+
+use axum::http::StatusCode;
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use utils::{DummyhttpProcess, Error};
+
+/// Requests are forwarded to the upstream and recorded.
+#[test]
+fn proxies_and_records_requests() -> Result<(), Error> {
+    let upstream = DummyhttpProcess::new(vec![
+        "-c",
+        "201",
+        "-H",
+        "x-upstream:yes",
+        "-b",
+        "{{ request.path }}?{{ request.query.a }} {{ request.body }}",
+    ])?;
+    let recording = std::env::temp_dir().join(format!("dummyhttp-{}.jsonl", upstream.port));
+    let _ = std::fs::remove_file(&recording);
+    let dh = DummyhttpProcess::new(vec![
+        "--proxy-to".to_string(),
+        upstream.url.clone(),
+        "--record".to_string(),
+        recording.to_string_lossy().to_string(),
+    ])?;
+
+    let resp = Client::new()
+        .post(format!("{}/some/path?a=b", dh.url))
+        .header("authorization", "Bearer secret")
+        .header("cookie", "session=secret")
+        .body("hello")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(resp.headers().get("x-upstream").unwrap(), "yes");
+    assert_eq!(resp.text()?, "/some/path?b hello");
+
+    let recorded = std::fs::read_to_string(&recording)?;
+    let lines = recorded.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1);
+    let recorded: Value = serde_json::from_str(lines[0])?;
+    assert_eq!(recorded["request"]["method"], "POST");
+    assert_eq!(recorded["request"]["path"], "/some/path");
+    assert_eq!(recorded["request"]["query"], "a=b");
+    assert_eq!(recorded["request"]["body"], "hello");
+    let request_headers = recorded["request"]["headers"].as_array().unwrap();
+    assert!(request_headers.contains(&json!(["authorization", "<redacted>"])));
+    assert!(request_headers.contains(&json!(["cookie", "<redacted>"])));
+    assert!(!lines[0].contains("secret"));
+    assert_eq!(recorded["response"]["status"], 201);
+    assert!(recorded["response"]["headers"]
+        .as_array()
+        .unwrap()
+        .contains(&json!(["x-upstream", "yes"])));
+    assert_eq!(recorded["response"]["body"], "/some/path?b hello");
+
+    std::fs::remove_file(&recording)?;
+
+    Ok(())
+}
+
+/// Failing to write the recording doesn't keep the upstream response from the client.
+#[cfg(target_os = "linux")]
+#[test]
+fn returns_response_if_recording_fails() -> Result<(), Error> {
+    let upstream = DummyhttpProcess::new(vec!["-c", "201", "-b", "upstream"])?;
+    let dh = DummyhttpProcess::new(vec![
+        "--proxy-to".to_string(),
+        upstream.url.clone(),
+        "--record".to_string(),
+        "/dev/full".to_string(),
+    ])?;
+
+    let resp = Client::new().get(&dh.url).send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(resp.text()?, "upstream");
+
+    Ok(())
+}
+
+/// Unreachable upstreams result in 502 Bad Gateway.
+#[test]
+fn unreachable_upstream_is_bad_gateway() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--proxy-to", "http://localhost:1"])?;
+
+    let resp = Client::new().get(&dh.url).send()?;
+    assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+
+    Ok(())
+}
+
+/// Recorded responses are replayed in order and unknown requests get the default response.
+#[test]
+fn replays_recorded_responses() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--replay", "tests/data/replay.jsonl"])?;
+
+    let client = Client::new();
+
+    let resp = client.get(format!("{}/users/1", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    let cookies = resp
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(cookies, ["session=abc", "theme=dark"]);
+    assert_eq!(resp.text()?, r#"{"id": 1}"#);
+
+    let jobs_url = format!("{}/jobs?async=true", dh.url);
+    let resp = client.post(&jobs_url).body("first").send()?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp = client.post(&jobs_url).body("first").send()?;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let resp = client.post(&jobs_url).body("first").send()?;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let resp = client.get(format!("{}/binary", dh.url)).send()?;
+    assert_eq!(resp.bytes()?.as_ref(), &[0xde, 0xad, 0xbe, 0xef]);
+
+    // Headers recorded as map by earlier versions are still understood.
+    let resp = client.get(format!("{}/legacy", dh.url)).send()?;
+    assert_eq!(resp.headers().get("content-type").unwrap(), "text/plain");
+    assert_eq!(resp.text()?, "legacy");
+
+    let resp = client.post(&jobs_url).body("other").send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+mod utils;
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use assert_cmd::prelude::*;
+use serde_json::Value;
+use utils::Error;
+
+/// Kills the wrapped dummyhttp once dropped
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+/// Start dummyhttp and return it along with the first line it prints
+fn first_line(args: &[&str]) -> Result<(KillOnDrop, String), Error> {
+    let mut child = KillOnDrop(
+        Command::cargo_bin("dummyhttp")?
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()?,
+    );
+    let mut line = String::new();
+    BufReader::new(child.0.stdout.as_mut().unwrap()).read_line(&mut line)?;
+    Ok((child, line))
+}
+
+/// With --port 0 the address the OS picked is printed.
+#[test]
+fn prints_address_as_json() -> Result<(), Error> {
+    let (dh, line) = first_line(&["-i", "127.0.0.1", "-p", "0", "--print-address", "json"])?;
+
+    let ready = serde_json::from_str::<Value>(&line)?;
+    let port = ready["port"].as_u64().unwrap();
+    assert_ne!(port, 0);
+    assert_eq!(ready["url"], format!("http://127.0.0.1:{port}"));
+    assert_eq!(
+        ready["urls"],
+        serde_json::json!([format!("http://127.0.0.1:{port}")])
+    );
+    assert_eq!(ready["pid"], dh.0.id());
+
+    let resp = reqwest::blocking::get(ready["url"].as_str().unwrap())?;
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
+
+/// The plain address is printed even when quiet.
+#[test]
+fn prints_plain_address_when_quiet() -> Result<(), Error> {
+    let (_dh, line) = first_line(&[
+        "-q",
+        "--listen",
+        "127.0.0.1:0",
+        "--listen",
+        "[::1]:0",
+        "--print-address",
+        "plain",
+    ])?;
+
+    assert!(line.starts_with("http://127.0.0.1:"));
+    assert!(!line.starts_with("http://127.0.0.1:0"));
+    let resp = reqwest::blocking::get(line.trim())?;
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
+
+/// Listeners on all interfaces are reported with an address clients can connect to.
+#[test]
+fn reports_loopback_for_unspecified_address() -> Result<(), Error> {
+    let (_dh, line) = first_line(&[
+        "--listen",
+        "0.0.0.0:0",
+        "--listen",
+        "[::]:0",
+        "--print-address",
+        "json",
+    ])?;
+
+    let ready = serde_json::from_str::<Value>(&line)?;
+    let urls = ready["urls"].as_array().unwrap();
+    assert!(urls[0].as_str().unwrap().starts_with("http://127.0.0.1:"));
+    assert!(urls[1].as_str().unwrap().starts_with("http://[::1]:"));
+    let resp = reqwest::blocking::get(ready["url"].as_str().unwrap())?;
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    Ok(())
+}
+
+/// The banner shows the port the OS picked.
+#[test]
+fn banner_shows_picked_port() -> Result<(), Error> {
+    let (_dh, line) = first_line(&["-i", "127.0.0.1", "-p", "0", "--log-format", "json"])?;
+
+    let message = serde_json::from_str::<Value>(&line)?["message"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(message.contains("listening on http://127.0.0.1:"));
+    assert!(!message.ends_with("127.0.0.1:0"));
+
+    Ok(())
+}
+
+/// --ready-file is written once dummyhttp accepts connections.
+#[test]
+fn writes_ready_file() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("dummyhttp-ready-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let dh = KillOnDrop(
+        Command::cargo_bin("dummyhttp")?
+            .args(["-q", "-i", "127.0.0.1", "-p", "0", "--ready-file"])
+            .arg(&path)
+            .spawn()?,
+    );
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) && !path.exists() {
+        sleep(Duration::from_millis(20));
+    }
+    let ready = serde_json::from_str::<Value>(&std::fs::read_to_string(&path)?)?;
+    assert_eq!(ready["pid"], dh.0.id());
+    let resp = reqwest::blocking::get(format!("http://127.0.0.1:{}", ready["port"]))?;
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+/// Unix sockets have no port.
+#[test]
+#[cfg(unix)]
+fn unix_socket_has_no_port() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("dummyhttp-ready-{}.sock", std::process::id()));
+    let (_dh, line) = first_line(&[
+        "--unix-socket",
+        path.to_str().unwrap(),
+        "--print-address",
+        "json",
+    ])?;
+
+    let ready = serde_json::from_str::<Value>(&line)?;
+    assert_eq!(ready["url"], format!("http://unix:{}", path.display()));
+    assert!(ready["port"].is_null());
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
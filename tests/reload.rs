@@ -0,0 +1,129 @@
+mod utils;
+
+use std::fs;
+use std::thread::sleep;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use utils::{DummyhttpProcess, Error};
+
+/// Long enough for the file watcher to notice a change.
+const RELOAD_WAIT: Duration = Duration::from_millis(2500);
+
+/// A config file in the temp dir that tests can freely change.
+fn config_file(name: &str, content: &str) -> Result<String, Error> {
+    let path = std::env::temp_dir().join(format!(
+        "dummyhttp-reload-{}-{}.yaml",
+        std::process::id(),
+        name
+    ));
+    fs::write(&path, content)?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+const BEFORE: &str = "
+routes:
+  - path: /hello
+    body: before
+";
+
+const AFTER: &str = "
+routes:
+  - path: /hello
+    body: after
+  - path: /new
+    code: 201
+";
+
+/// Changes to the config file are picked up without restarting.
+#[test]
+fn reloads_changed_config() -> Result<(), Error> {
+    let config = config_file("changed", BEFORE)?;
+    let dh = DummyhttpProcess::new(vec![
+        "-q".to_string(),
+        "--config".to_string(),
+        config.clone(),
+    ])?;
+
+    let resp = reqwest::blocking::get(format!("{}/hello", dh.url))?;
+    assert_eq!(resp.text()?, "before");
+
+    fs::write(&config, AFTER)?;
+    sleep(RELOAD_WAIT);
+
+    let resp = reqwest::blocking::get(format!("{}/hello", dh.url))?;
+    assert_eq!(resp.text()?, "after");
+    let resp = reqwest::blocking::get(format!("{}/new", dh.url))?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    fs::remove_file(config)?;
+
+    Ok(())
+}
+
+/// If the changed config is invalid, the previous routes stay active.
+#[test]
+fn keeps_routes_on_invalid_config() -> Result<(), Error> {
+    let config = config_file("invalid", BEFORE)?;
+    let dh = DummyhttpProcess::new(vec![
+        "-q".to_string(),
+        "--config".to_string(),
+        config.clone(),
+    ])?;
+    dh.wait_started()?;
+
+    fs::write(&config, "routes: [")?;
+    sleep(RELOAD_WAIT);
+
+    let resp = reqwest::blocking::get(format!("{}/hello", dh.url))?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "before");
+
+    fs::write(&config, AFTER)?;
+    sleep(RELOAD_WAIT);
+
+    let resp = reqwest::blocking::get(format!("{}/hello", dh.url))?;
+    assert_eq!(resp.text()?, "after");
+
+    fs::remove_file(config)?;
+
+    Ok(())
+}
+
+/// SIGHUP reloads the config and TLS certificate even if no file changed.
+#[cfg(unix)]
+#[test]
+fn reloads_on_sighup() -> Result<(), Error> {
+    use std::io::Read;
+    use std::process::Command;
+
+    let mut dh = DummyhttpProcess::new(vec![
+        "--config",
+        "tests/data/routes.yaml",
+        "--tls-cert",
+        "tests/data/cert.pem",
+        "--tls-key",
+        "tests/data/key.pem",
+    ])?;
+    dh.wait_started()?;
+
+    let status = Command::new("kill")
+        .arg("-HUP")
+        .arg(dh.child.id().to_string())
+        .status()?;
+    assert!(status.success());
+    sleep(Duration::from_millis(500));
+
+    dh.child.kill()?;
+    let mut output = String::new();
+    dh.child
+        .stdout
+        .as_mut()
+        .unwrap()
+        .read_to_string(&mut output)?;
+
+    assert!(output.contains("Reloaded configuration"));
+    assert!(output.contains("Reloaded TLS certificate"));
+
+    Ok(())
+}
@@ -121,3 +121,40 @@ fn returns_custom_headers(method: Method) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// The incoming request is available in body templates.
+#[test]
+fn returns_templated_body_from_request() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "-b",
+        "{{ request.method }} {{ request.path }} {{ request.query.a }} {{ request.headers['x-test'] }} {{ request.json.id }}",
+    ])?;
+
+    let client = Client::new();
+    let resp = client
+        .post(format!("{}/some/path?a=b&c=d", dh.url))
+        .header("x-test", "header")
+        .body(r#"{"id": 1337}"#)
+        .send()?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "POST /some/path b header 1337");
+
+    Ok(())
+}
+
+/// Path captures of the matched route are available in body templates.
+#[test]
+fn returns_templated_body_from_path_params() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["--config", "tests/data/templates.yaml"])?;
+
+    let client = Client::new();
+    let resp = client
+        .get(format!("{}/users/42/some/more", dh.url))
+        .send()?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "42 some/more");
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+mod utils;
+
+use axum::http::StatusCode;
+use reqwest::blocking::Client;
+use serde_json::Value;
+use utils::{DummyhttpProcess, Error};
+
+/// Routes of a scenario only match in their required state and move the scenario along.
+#[test]
+fn scenario_transitions_between_states() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(["--config", "tests/data/scenarios.yaml"])?;
+    let client = Client::new();
+
+    let resp = client.get(format!("{}/jobs/1", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = client.post(format!("{}/jobs", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    let resp = client.get(format!("{}/jobs/1", dh.url)).send()?;
+    assert_eq!(resp.text()?, "1 pending");
+    let resp = client.get(format!("{}/jobs/1", dh.url)).send()?;
+    assert_eq!(resp.text()?, "1 done");
+    let resp = client.get(format!("{}/jobs/1", dh.url)).send()?;
+    assert_eq!(resp.text()?, "1 done");
+
+    Ok(())
+}
+
+/// Scenario states can be listed and reset via the admin API.
+#[test]
+fn scenarios_can_be_listed_and_reset() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(["--config", "tests/data/scenarios.yaml"])?;
+    let client = Client::new();
+
+    let states = |client: &Client| -> Result<Value, Error> {
+        let resp = client
+            .get(format!("{}/__dummyhttp/scenarios", dh.url))
+            .send()?;
+        Ok(serde_json::from_str(&resp.text()?)?)
+    };
+    assert_eq!(states(&client)?["job"], "started");
+
+    client.post(format!("{}/jobs", dh.url)).send()?;
+    assert_eq!(states(&client)?["job"], "pending");
+
+    let resp = client
+        .delete(format!("{}/__dummyhttp/scenarios/job", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(states(&client)?["job"], "started");
+
+    client.post(format!("{}/jobs", dh.url)).send()?;
+    let resp = client
+        .delete(format!("{}/__dummyhttp/scenarios", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(states(&client)?["job"], "started");
+
+    let resp = client
+        .delete(format!("{}/__dummyhttp/scenarios/nope", dh.url))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+/// Requests that fail to get the configured response don't move the scenario along.
+#[test]
+fn failed_responses_keep_state() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(["--config", "tests/data/scenarios.yaml"])?;
+    let client = Client::new();
+
+    client.post(format!("{}/jobs", dh.url)).send()?;
+    let resp = client.post(format!("{}/jobs/1/cancel", dh.url)).send()?;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let resp = client.get(format!("{}/jobs/1", dh.url)).send()?;
+    assert_eq!(resp.text()?, "1 pending");
+
+    Ok(())
+}
+
+/// Concurrent requests can't all match a route before it moves the scenario along.
+#[test]
+fn concurrent_requests_transition_once() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(["--config", "tests/data/scenarios.yaml"])?;
+    let url = format!("{}/report", dh.url);
+
+    let requests = (0..5)
+        .map(|_| {
+            let url = url.clone();
+            std::thread::spawn(move || -> Result<String, reqwest::Error> {
+                reqwest::blocking::get(url)?.text()
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut bodies = requests
+        .into_iter()
+        .map(|request| request.join().unwrap())
+        .collect::<Result<Vec<_>, _>>()?;
+    bodies.sort();
+    assert_eq!(bodies, ["pending", "ready", "ready", "ready", "ready"]);
+
+    Ok(())
+}
@@ -0,0 +1,246 @@
+#![cfg(unix)]
+
+mod utils;
+
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde_json::Value;
+use utils::{DummyhttpProcess, Error};
+
+/// A summary file unique to this test
+fn summary_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "dummyhttp-summary-{}-{name}.json",
+        std::process::id()
+    ))
+}
+
+/// Send SIGTERM and wait for dummyhttp to exit
+fn terminate(dh: &mut DummyhttpProcess) -> Result<(ExitStatus, Duration), Error> {
+    let start = Instant::now();
+    unsafe { libc::kill(dh.child.id() as i32, libc::SIGTERM) };
+    while start.elapsed() < Duration::from_secs(5) {
+        if let Some(status) = dh.child.try_wait()? {
+            return Ok((status, start.elapsed()));
+        }
+        sleep(Duration::from_millis(20));
+    }
+    Err("dummyhttp didn't exit".into())
+}
+
+/// The summary counts requests per route and lists unmatched ones.
+#[test]
+fn writes_summary_on_sigterm() -> Result<(), Error> {
+    let path = summary_file("routes");
+    let mut dh = DummyhttpProcess::new(vec![
+        "-q".to_string(),
+        "--config".to_string(),
+        "tests/data/routes.yaml".to_string(),
+        "--summary-file".to_string(),
+        path.to_string_lossy().into_owned(),
+    ])?;
+
+    let client = Client::new();
+    client.get(format!("{}/jobs/1", dh.url)).send()?;
+    client.get(format!("{}/jobs/2", dh.url)).send()?;
+    client.delete(format!("{}/jobs/3", dh.url)).send()?;
+    client
+        .get(format!("{}/__dummyhttp/requests", dh.url))
+        .send()?;
+
+    // The client keeps its connection open, which mustn't hold up the shutdown.
+    let (status, elapsed) = terminate(&mut dh)?;
+    assert!(status.success());
+    assert!(elapsed < Duration::from_secs(2));
+
+    let summary = serde_json::from_str::<Value>(&std::fs::read_to_string(&path)?)?;
+    assert_eq!(summary["requests"], 3);
+    assert_eq!(summary["routes"]["route #2 (/jobs/:id)"], 2);
+    assert_eq!(summary["routes"]["default"], 1);
+    assert_eq!(summary["unmatched"]["count"], 1);
+    let unmatched = &summary["unmatched"]["requests"][0];
+    assert_eq!(unmatched["method"], "DELETE");
+    assert_eq!(unmatched["uri"], "/jobs/3");
+    assert!(unmatched["reason"]
+        .as_str()
+        .unwrap()
+        .contains("doesn't accept DELETE"));
+    assert_eq!(summary["errors"]["count"], 0);
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+/// Requests dummyhttp failed to answer as configured are listed as errors.
+#[test]
+fn summary_lists_errors() -> Result<(), Error> {
+    let path = summary_file("errors");
+    let mut dh = DummyhttpProcess::new(vec![
+        "-q".to_string(),
+        "-b".to_string(),
+        "{{ nope }}".to_string(),
+        "--summary-file".to_string(),
+        path.to_string_lossy().into_owned(),
+    ])?;
+
+    let resp = reqwest::blocking::get(format!("{}/broken", dh.url))?;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    terminate(&mut dh)?;
+
+    let summary = serde_json::from_str::<Value>(&std::fs::read_to_string(&path)?)?;
+    assert_eq!(summary["errors"]["count"], 1);
+    let error = &summary["errors"]["requests"][0];
+    assert_eq!(error["uri"], "/broken");
+    assert_eq!(error["status"], 500);
+    assert!(error["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Failed to render body template"));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+/// Without --summary-file the summary is printed.
+#[test]
+fn prints_summary_on_sigterm() -> Result<(), Error> {
+    let mut dh = DummyhttpProcess::new(vec!["--log-format", "json"])?;
+    reqwest::blocking::get(&dh.url)?;
+    terminate(&mut dh)?;
+
+    let mut output = String::new();
+    dh.child
+        .stdout
+        .as_mut()
+        .unwrap()
+        .read_to_string(&mut output)?;
+    let lines = output
+        .lines()
+        .map(serde_json::from_str::<Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert!(lines[2]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Received SIGTERM, waiting up to 10s"));
+    assert_eq!(lines[3]["message"], "Shutdown summary");
+    assert_eq!(lines[3]["summary"]["requests"], 1);
+    assert_eq!(lines[3]["summary"]["routes"]["default"], 1);
+
+    Ok(())
+}
+
+/// Requests in flight when the signal arrives are finished.
+#[test]
+fn drains_open_requests() -> Result<(), Error> {
+    let mut dh = DummyhttpProcess::new(vec!["-q", "--delay", "500ms", "-b", "finished"])?;
+
+    let url = dh.url.clone();
+    let request = thread::spawn(move || reqwest::blocking::get(url).and_then(|resp| resp.text()));
+    sleep(Duration::from_millis(200));
+    let (status, _) = terminate(&mut dh)?;
+
+    assert!(status.success());
+    assert_eq!(request.join().unwrap()?, "finished");
+
+    Ok(())
+}
+
+/// Requests still open after --shutdown-timeout are cut off.
+#[test]
+fn cuts_off_requests_after_timeout() -> Result<(), Error> {
+    let mut dh =
+        DummyhttpProcess::new(vec!["-q", "--delay", "10s", "--shutdown-timeout", "200ms"])?;
+
+    let url = dh.url.clone();
+    let request = thread::spawn(move || reqwest::blocking::get(url).and_then(|resp| resp.text()));
+    sleep(Duration::from_millis(200));
+    let (status, elapsed) = terminate(&mut dh)?;
+
+    assert!(status.success());
+    assert!(elapsed < Duration::from_secs(2));
+    assert!(request.join().unwrap().is_err());
+
+    Ok(())
+}
+
+/// Unix sockets are drained as well, and their socket file is removed.
+#[test]
+fn drains_unix_socket() -> Result<(), Error> {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use std::process::{Command, Stdio};
+
+    use assert_cmd::prelude::*;
+
+    let path = std::env::temp_dir().join(format!("dummyhttp-shutdown-{}.sock", std::process::id()));
+    let mut child = Command::cargo_bin("dummyhttp")?
+        .args(["-q", "--delay", "500ms", "-b", "finished", "--unix-socket"])
+        .arg(&path)
+        .stdout(Stdio::null())
+        .spawn()?;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) && UnixStream::connect(&path).is_err() {
+        sleep(Duration::from_millis(50));
+    }
+
+    let mut stream = UnixStream::connect(&path)?;
+    write!(
+        stream,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+    )?;
+    sleep(Duration::from_millis(200));
+    unsafe { libc::kill(child.id() as i32, libc::SIGTERM) };
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    assert!(response.ends_with("finished"));
+    assert!(child.wait()?.success());
+    assert!(!path.exists(), "socket file is left behind");
+    Ok(())
+}
+
+/// Idle keep-alive connections on Unix sockets are closed right away as well.
+#[test]
+fn closes_idle_unix_connections() -> Result<(), Error> {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use std::process::{Command, Stdio};
+
+    use assert_cmd::prelude::*;
+
+    let path = std::env::temp_dir().join(format!("dummyhttp-idle-{}.sock", std::process::id()));
+    let mut child = Command::cargo_bin("dummyhttp")?
+        .args(["-q", "--unix-socket"])
+        .arg(&path)
+        .stdout(Stdio::null())
+        .spawn()?;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) && UnixStream::connect(&path).is_err() {
+        sleep(Duration::from_millis(50));
+    }
+
+    let mut stream = UnixStream::connect(&path)?;
+    write!(stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+    let mut response = vec![];
+    let mut buf = [0; 1024];
+    while !response.ends_with(b"dummyhttp") {
+        let read = stream.read(&mut buf)?;
+        assert_ne!(read, 0);
+        response.extend_from_slice(&buf[..read]);
+    }
+
+    let start = Instant::now();
+    unsafe { libc::kill(child.id() as i32, libc::SIGTERM) };
+    assert_eq!(stream.read(&mut buf)?, 0);
+    assert!(child.wait()?.success());
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
@@ -0,0 +1,93 @@
+mod utils;
+
+use std::io::Read;
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+use axum::http::StatusCode;
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use utils::{DummyhttpProcess, Error};
+
+/// All events are streamed in order and rendered with the request available.
+#[test]
+fn sse_streams_events() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--config", "tests/data/sse.yaml"])?;
+
+    let resp = reqwest::blocking::get(format!("{}/events?name=alice", dh.url))?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], "text/event-stream");
+    assert_eq!(resp.headers()["cache-control"], "no-cache");
+    assert_eq!(
+        resp.text()?,
+        "id: 0\nevent: greeting\ndata: hello alice\n\n\
+         id: 1\nretry: 500\ndata: first line\ndata: second line\n\n\
+         id: 2\ndata: bye\n\n"
+    );
+
+    Ok(())
+}
+
+/// With `resume`, the stream continues after the event given in `Last-Event-ID`.
+#[test]
+fn sse_resumes_after_last_event_id() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--config", "tests/data/sse.yaml"])?;
+
+    let resp = Client::new()
+        .get(format!("{}/events", dh.url))
+        .header("Last-Event-ID", "0")
+        .send()?;
+    assert_eq!(
+        resp.text()?,
+        "id: 1\nretry: 500\ndata: first line\ndata: second line\n\n\
+         id: 2\ndata: bye\n\n"
+    );
+
+    // Unknown ids start from the beginning.
+    let resp = Client::new()
+        .get(format!("{}/events?name=bob", dh.url))
+        .header("Last-Event-ID", "nope")
+        .send()?;
+    assert!(resp
+        .text()?
+        .starts_with("id: 0\nevent: greeting\ndata: hello bob\n\n"));
+
+    Ok(())
+}
+
+/// Repeating streams start over with the first event after the last one.
+#[test]
+fn sse_repeats_events() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--config", "tests/data/sse.yaml"])?;
+
+    let mut resp = reqwest::blocking::get(format!("{}/ticks", dh.url))?;
+    let expected = "data: tick 0\n\ndata: tock 0\n\ndata: tick 1\n\ndata: tock 1\n\n";
+    let mut received = vec![0; expected.len()];
+    resp.read_exact(&mut received)?;
+    assert_eq!(String::from_utf8(received)?, expected);
+
+    Ok(())
+}
+
+/// Events with empty data still get a data line so that clients don't drop them.
+#[test]
+fn sse_sends_empty_data() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--config", "tests/data/sse.yaml"])?;
+
+    let resp = reqwest::blocking::get(format!("{}/empty", dh.url))?;
+    assert_eq!(resp.text()?, "event: ping\ndata: \n\n");
+
+    Ok(())
+}
+
+/// Routes can't stream events and a body at the same time.
+#[test]
+fn sse_with_body_settings_fails() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--config", "tests/data/invalid_sse.yaml"])
+        .assert()
+        .failure()
+        .stderr(contains("sse can't be combined with body"));
+
+    Ok(())
+}
@@ -1,9 +1,14 @@
+#![cfg(feature = "tls")]
+
 mod utils;
 
 use assert_cmd::prelude::*;
 use axum::http::StatusCode;
+use dummyhttp::DummyServer;
 use predicates::str::contains;
 use reqwest::blocking::ClientBuilder;
+use reqwest::{Certificate, Identity};
+use std::io::Read;
 use std::process::Command;
 use utils::{DummyhttpProcess, Error};
 
@@ -32,7 +37,7 @@ fn tls_works() -> Result<(), Error> {
 #[test]
 fn wrong_path_cert() -> Result<(), Error> {
     Command::cargo_bin("dummyhttp")?
-        .args(&["--tls-cert", "wrong", "--tls-key", "tests/data/key.pem"])
+        .args(["--tls-cert", "wrong", "--tls-key", "tests/data/key.pem"])
         .assert()
         .failure()
         .stderr(contains(
@@ -46,7 +51,7 @@ fn wrong_path_cert() -> Result<(), Error> {
 #[test]
 fn wrong_path_key() -> Result<(), Error> {
     Command::cargo_bin("dummyhttp")?
-        .args(&["--tls-cert", "tests/data/cert.pem", "--tls-key", "wrong"])
+        .args(["--tls-cert", "tests/data/cert.pem", "--tls-key", "wrong"])
         .assert()
         .failure()
         .stderr(contains(
@@ -55,3 +60,207 @@ fn wrong_path_key() -> Result<(), Error> {
 
     Ok(())
 }
+
+/// A self-signed certificate is generated whose CA can be written out for clients to trust.
+#[test]
+fn tls_self_signed_works() -> Result<(), Error> {
+    let ca_path = std::env::temp_dir().join(format!("dummyhttp-ca-{}.pem", std::process::id()));
+    let mut dh = DummyhttpProcess::new(vec![
+        "--tls-self-signed".to_string(),
+        "--tls-san".to_string(),
+        "dummy.test".to_string(),
+        "--tls-write-ca".to_string(),
+        ca_path.to_string_lossy().into_owned(),
+    ])?;
+    dh.wait_started()?;
+
+    let ca = Certificate::from_pem(&std::fs::read(&ca_path)?)?;
+    let client = ClientBuilder::new().add_root_certificate(ca).build()?;
+    let resp = client.get(&dh.url).send()?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text()?, "dummyhttp");
+
+    dh.child.kill()?;
+    let mut output = String::new();
+    dh.child
+        .stdout
+        .as_mut()
+        .unwrap()
+        .read_to_string(&mut output)?;
+    assert!(output.contains("https://"));
+    assert!(output.contains("CA SHA-256 fingerprint: "));
+
+    std::fs::remove_file(ca_path)?;
+
+    Ok(())
+}
+
+/// The CA of a self-signed certificate is available from the library.
+#[test]
+fn tls_self_signed_library() -> Result<(), Error> {
+    let server = DummyServer::builder()
+        .args(["-q", "--tls-self-signed"])
+        .start()?;
+    assert!(server.url().starts_with("https://"));
+
+    let ca = Certificate::from_pem(server.ca_certificate().unwrap().as_bytes())?;
+    let client = ClientBuilder::new().add_root_certificate(ca).build()?;
+    let resp = client
+        .get(format!("https://localhost:{}", server.addr().port()))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+/// A self-signed certificate can't be combined with a given one.
+#[test]
+fn tls_self_signed_conflicts_with_cert() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args([
+            "--tls-self-signed",
+            "--tls-cert",
+            "tests/data/cert.pem",
+            "--tls-key",
+            "tests/data/key.pem",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+
+    Command::cargo_bin("dummyhttp")?
+        .args(["--tls-san", "dummy.test"])
+        .assert()
+        .failure()
+        .stderr(contains("--tls-self-signed"));
+
+    Ok(())
+}
+
+fn client_identity(cert: &str, key: &str) -> Result<Identity, Error> {
+    let mut pem = std::fs::read(cert)?;
+    pem.extend(std::fs::read(key)?);
+    Ok(Identity::from_pem(&pem)?)
+}
+
+/// With --tls-client-ca, clients have to present a certificate which is logged and available to
+/// templates.
+#[test]
+fn mtls_requires_client_cert() -> Result<(), Error> {
+    let mut dh = DummyhttpProcess::new(vec![
+        "--tls-cert",
+        "tests/data/cert.pem",
+        "--tls-key",
+        "tests/data/key.pem",
+        "--tls-client-ca",
+        "tests/data/client_ca.pem",
+        "--log-format",
+        "json",
+        "--body",
+        "{{ request.client_cert.subject }} {{ request.client_cert.sans | join(sep=',') }}",
+    ])?;
+
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .identity(client_identity(
+            "tests/data/client_cert.pem",
+            "tests/data/client_key.pem",
+        )?)
+        .build()?;
+    let resp = client.get(&dh.url).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.text()?,
+        "CN=client, O=dummyhttp DNS:client.dummyhttp.test,IP:127.0.0.1"
+    );
+
+    // Without a certificate, the handshake fails.
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    assert!(client.get(&dh.url).send().is_err());
+
+    // The same goes for certificates not signed by the CA.
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .identity(client_identity(
+            "tests/data/cert.pem",
+            "tests/data/key.pem",
+        )?)
+        .build()?;
+    assert!(client.get(&dh.url).send().is_err());
+
+    dh.child.kill()?;
+    let mut output = String::new();
+    dh.child
+        .stdout
+        .as_mut()
+        .unwrap()
+        .read_to_string(&mut output)?;
+    let record: serde_json::Value = output
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .find(|record: &serde_json::Value| record.get("client_cert").is_some())
+        .expect("No request with a client certificate was logged");
+    assert_eq!(record["client_cert"]["subject"], "CN=client, O=dummyhttp");
+    assert_eq!(
+        record["client_cert"]["sans"],
+        serde_json::json!(["DNS:client.dummyhttp.test", "IP:127.0.0.1"])
+    );
+    assert_eq!(
+        record["client_cert"]["fingerprint"],
+        "7C:70:7B:E0:F4:DA:29:AC:68:EB:44:20:14:2C:54:22:11:04:D6:F8:A5:B7:90:29:1B:93:3F:64:04:23:32:AE"
+    );
+
+    Ok(())
+}
+
+/// With --tls-client-auth optional, clients without a certificate are let through as well.
+#[test]
+fn mtls_optional_client_cert() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec![
+        "-q",
+        "--tls-cert",
+        "tests/data/cert.pem",
+        "--tls-key",
+        "tests/data/key.pem",
+        "--tls-client-ca",
+        "tests/data/client_ca.pem",
+        "--tls-client-auth",
+        "optional",
+        "--body",
+        "{% if request.client_cert %}{{ request.client_cert.subject }}{% else %}anonymous{% endif %}",
+    ])?;
+
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    assert_eq!(client.get(&dh.url).send()?.text()?, "anonymous");
+
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .identity(client_identity(
+            "tests/data/client_cert.pem",
+            "tests/data/client_key.pem",
+        )?)
+        .build()?;
+    assert_eq!(
+        client.get(&dh.url).send()?.text()?,
+        "CN=client, O=dummyhttp"
+    );
+
+    Ok(())
+}
+
+/// A client CA requires TLS to be enabled.
+#[test]
+fn mtls_requires_tls() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--tls-client-ca", "tests/data/client_ca.pem"])
+        .assert()
+        .failure()
+        .stderr(contains("--tls-self-signed"));
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+#![cfg(unix)]
+
+mod utils;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use assert_cmd::prelude::*;
+use predicates::str::contains;
+use serde_json::Value;
+use utils::Error;
+
+/// A socket path unique to this test
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dummyhttp-{}-{name}.sock", std::process::id()))
+}
+
+/// Start dummyhttp with the given args and wait for the socket at `path` to accept connections
+fn spawn(path: &Path, args: &[&str]) -> Result<Child, Error> {
+    let child = Command::cargo_bin("dummyhttp")?
+        .arg("--unix-socket")
+        .arg(path)
+        .args(args)
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) && UnixStream::connect(path).is_err() {
+        sleep(Duration::from_millis(100));
+    }
+    Ok(child)
+}
+
+/// Send a plain HTTP/1.1 request over the socket at `path` and return the raw response
+fn get(path: &Path, uri: &str) -> Result<String, Error> {
+    let mut stream = UnixStream::connect(path)?;
+    write!(
+        stream,
+        "GET {uri} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+/// Requests can be served over a Unix socket and the log shows the client's credentials.
+#[test]
+fn serves_over_unix_socket() -> Result<(), Error> {
+    let path = socket_path("serve");
+    let mut child = spawn(&path, &["--log-format", "json", "-b", "over uds"])?;
+
+    let response = get(&path, "/hello")?;
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.ends_with("over uds"));
+
+    child.kill()?;
+    let mut output = String::new();
+    child.stdout.as_mut().unwrap().read_to_string(&mut output)?;
+    let record = output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .find(|record| record.get("method").is_some())
+        .unwrap();
+    assert_eq!(record["uri"], "/hello");
+    let peer = record["peer"].as_str().unwrap();
+    assert!(peer.starts_with("unix:pid="), "unexpected peer {}", peer);
+    assert!(peer.contains(&format!("pid={}", std::process::id())));
+
+    let _ = fs::remove_file(&path);
+    Ok(())
+}
+
+/// Sockets left behind by a previous run are replaced.
+#[test]
+fn replaces_stale_socket() -> Result<(), Error> {
+    let path = socket_path("stale");
+    let mut child = spawn(&path, &[])?;
+    child.kill()?;
+    child.wait()?;
+    assert!(fs::symlink_metadata(&path)?.file_type().is_socket());
+
+    let mut child = spawn(&path, &["-b", "again"])?;
+    assert!(get(&path, "/")?.ends_with("again"));
+
+    child.kill()?;
+    let _ = fs::remove_file(&path);
+    Ok(())
+}
+
+/// A socket somebody is still listening on is left alone.
+#[test]
+fn refuses_socket_in_use() -> Result<(), Error> {
+    let path = socket_path("in-use");
+    let mut child = spawn(&path, &[])?;
+
+    Command::cargo_bin("dummyhttp")?
+        .arg("--unix-socket")
+        .arg(&path)
+        .assert()
+        .failure()
+        .stderr(contains("Another process is listening on"));
+
+    child.kill()?;
+    let _ = fs::remove_file(&path);
+    Ok(())
+}
+
+/// Files which aren't sockets are never removed.
+#[test]
+fn refuses_to_replace_regular_file() -> Result<(), Error> {
+    let path = socket_path("regular");
+    fs::write(&path, "precious")?;
+
+    Command::cargo_bin("dummyhttp")?
+        .arg("--unix-socket")
+        .arg(&path)
+        .assert()
+        .failure()
+        .stderr(contains("exists and is not a socket"));
+    assert_eq!(fs::read_to_string(&path)?, "precious");
+
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+/// --unix-socket-mode sets the permissions of the socket.
+#[test]
+fn sets_socket_mode() -> Result<(), Error> {
+    let path = socket_path("mode");
+    let mut child = spawn(&path, &["--unix-socket-mode", "600"])?;
+
+    let mode = fs::metadata(&path)?.permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    child.kill()?;
+    let _ = fs::remove_file(&path);
+    Ok(())
+}
+
+/// Invalid Unix socket options are rejected.
+#[test]
+fn invalid_unix_socket_options_fail() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--unix-socket-mode", "999"])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid mode '999'"));
+    Command::cargo_bin("dummyhttp")?
+        .args(["--listen", "unix:/tmp/nope.sock+tls"])
+        .assert()
+        .failure()
+        .stderr(contains("TLS is not supported on Unix sockets"));
+    Command::cargo_bin("dummyhttp")?
+        .args(["--unix-socket", "/tmp/nope.sock", "-p", "8080"])
+        .assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+
+    Ok(())
+}
+
+/// Connection-level faults work on Unix sockets as well.
+#[test]
+fn injects_faults_over_unix_socket() -> Result<(), Error> {
+    let path = socket_path("faults");
+    let mut child = spawn(&path, &["-q", "--fault", "malformed"])?;
+
+    let response = get(&path, "/")?;
+    assert!(response.starts_with("HTTP/1.1 ??? Malformed"));
+
+    child.kill()?;
+    let _ = fs::remove_file(&path);
+    Ok(())
+}
+
+/// Connections over Unix sockets are counted as active connections.
+#[test]
+fn counts_unix_connections() -> Result<(), Error> {
+    let path = socket_path("metrics");
+    let mut child = spawn(&path, &["-q"])?;
+
+    let _idle = UnixStream::connect(&path)?;
+    let response = get(&path, "/__dummyhttp/metrics")?;
+    assert!(response.contains("dummyhttp_active_connections 2"));
+
+    child.kill()?;
+    let _ = fs::remove_file(&path);
+    Ok(())
+}
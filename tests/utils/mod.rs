@@ -1,14 +1,12 @@
 use assert_cmd::prelude::*;
-use port_check::{free_local_port, is_port_reachable};
 use std::ffi::OsStr;
 use std::process::{Child, Command, Stdio};
-use std::thread::sleep;
-use std::time::{Duration, Instant};
 
 /// Error type used by tests
 pub type Error = Box<dyn std::error::Error>;
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct DummyhttpProcess {
     pub child: Child,
     pub port: String,
@@ -31,26 +29,15 @@ impl DummyhttpProcess {
         I: IntoIterator<Item = S> + Clone + std::fmt::Debug,
         S: AsRef<OsStr> + PartialEq + From<&'static str>,
     {
-        let port = free_local_port()
-            .expect("Couldn't find a free local port")
-            .to_string();
-
-        let child = Command::cargo_bin("dummyhttp")?
-            .arg("-p")
-            .arg(&port)
-            .args(args.clone())
-            .stdout(Stdio::piped())
-            .spawn()?;
-
-        // Wait a max of 1s for the port to become available.
-        let start_wait = Instant::now();
-        while start_wait.elapsed().as_secs() < 1
-            && !is_port_reachable(format!("localhost:{}", port))
-        {
-            sleep(Duration::from_millis(100));
-        }
+        let mut command = Command::cargo_bin("dummyhttp")?;
+        command.args(args.clone()).stdout(Stdio::piped());
+        let (child, port) = spawn_listening(command)?;
+        let port = port.to_string();
 
-        let proto = if args.into_iter().any(|x| x == "--tls-cert".into()) {
+        let proto = if args
+            .into_iter()
+            .any(|x| x == "--tls-cert".into() || x == "--tls-self-signed".into())
+        {
             "https".to_string()
         } else {
             "http".to_string()
@@ -59,4 +46,72 @@ impl DummyhttpProcess {
 
         Ok(DummyhttpProcess { child, port, url })
     }
+
+    /// Wait until dummyhttp has started up by sending it a request
+    ///
+    /// Needed before changing files it reads or sending signals it handles once started.
+    pub fn wait_started(&self) -> Result<(), Error> {
+        reqwest::blocking::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()?
+            .get(&self.url)
+            .send()?;
+        Ok(())
+    }
+}
+
+/// Spawn dummyhttp on a port picked by the OS and wait for it to accept connections
+fn spawn_listening(mut command: Command) -> Result<(Child, u16), Error> {
+    use std::convert::TryFrom;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    static SPAWNED: AtomicUsize = AtomicUsize::new(0);
+    let ready_file = std::env::temp_dir().join(format!(
+        "dummyhttp-ready-{}-{}.json",
+        std::process::id(),
+        SPAWNED.fetch_add(1, Ordering::SeqCst)
+    ));
+    let child = command
+        .args(["-p", "0", "--ready-file"])
+        .arg(&ready_file)
+        .spawn()?;
+
+    // Wait a max of 1s for dummyhttp to start.
+    let start_wait = Instant::now();
+    while start_wait.elapsed().as_secs() < 1 && !ready_file.exists() {
+        sleep(Duration::from_millis(20));
+    }
+    let ready = std::fs::read_to_string(&ready_file)?;
+    std::fs::remove_file(&ready_file)?;
+    let port = serde_json::from_str::<serde_json::Value>(&ready)?["port"]
+        .as_u64()
+        .and_then(|port| u16::try_from(port).ok())
+        .ok_or("dummyhttp reported no port")?;
+    Ok((child, port))
+}
+
+/// Make `fd` available to the spawned process as file descriptor `target`
+#[cfg(unix)]
+#[allow(dead_code)]
+pub fn inherit_fd(command: &mut Command, fd: &impl std::os::unix::io::AsRawFd, target: i32) {
+    use std::os::unix::process::CommandExt;
+
+    let fd = fd.as_raw_fd();
+    // dup2 clears the close-on-exec flag of the new descriptor. If both are the same it has to be
+    // cleared by hand.
+    unsafe {
+        command.pre_exec(move || {
+            let result = if fd == target {
+                libc::fcntl(fd, libc::F_SETFD, 0)
+            } else {
+                libc::dup2(fd, target)
+            };
+            if result == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
 }
@@ -0,0 +1,151 @@
+mod utils;
+
+use std::io::Read;
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+use axum::http::StatusCode;
+use predicates::str::contains;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::Message;
+use utils::{DummyhttpProcess, Error};
+
+fn ws_url(dh: &DummyhttpProcess, path: &str) -> String {
+    format!("ws://localhost:{}{}", dh.port, path)
+}
+
+/// In echo mode, messages are sent back as they are.
+#[test]
+fn websocket_echo() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--config", "tests/data/websocket.yaml"])?;
+
+    let (mut socket, resp) = tungstenite::connect(ws_url(&dh, "/echo"))?;
+    assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+    socket.write_message(Message::Text("hi".to_string()))?;
+    assert_eq!(socket.read_message()?, Message::Text("hi".to_string()));
+    socket.write_message(Message::Binary(vec![1, 2, 3]))?;
+    assert_eq!(socket.read_message()?, Message::Binary(vec![1, 2, 3]));
+
+    Ok(())
+}
+
+/// A fixed sequence of messages is sent followed by a close frame with the given code.
+#[test]
+fn websocket_sends_sequence_and_closes() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--config", "tests/data/websocket.yaml"])?;
+
+    let (mut socket, _) = tungstenite::connect(ws_url(&dh, "/ticker"))?;
+    assert_eq!(socket.read_message()?, Message::Text("tick 1".to_string()));
+    assert_eq!(socket.read_message()?, Message::Text("tick 2".to_string()));
+    assert_eq!(socket.read_message()?, Message::Binary(vec![0, 1, 2]));
+    match socket.read_message()? {
+        Message::Close(Some(close)) => {
+            assert_eq!(close.code, CloseCode::from(4000));
+            assert_eq!(close.reason, "done");
+        }
+        other => panic!("Expected a close frame but got {:?}", other),
+    }
+
+    Ok(())
+}
+
+/// Incoming messages are answered by the first matching reply.
+#[test]
+fn websocket_replies_to_matching_messages() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--config", "tests/data/websocket.yaml"])?;
+
+    let (mut socket, _) = tungstenite::connect(ws_url(&dh, "/chat?name=alice"))?;
+    assert_eq!(
+        socket.read_message()?,
+        Message::Text("hello alice".to_string())
+    );
+
+    socket.write_message(Message::Text(r#"{"type": "ping"}"#.to_string()))?;
+    assert_eq!(
+        socket.read_message()?,
+        Message::Text(r#"{"type": "pong"}"#.to_string())
+    );
+
+    socket.write_message(Message::Text("what?".to_string()))?;
+    assert_eq!(
+        socket.read_message()?,
+        Message::Text("unknown: what?".to_string())
+    );
+    assert_eq!(
+        socket.read_message()?,
+        Message::Text("try again".to_string())
+    );
+
+    socket.write_message(Message::Text("bye".to_string()))?;
+    assert_eq!(socket.read_message()?, Message::Text("see you".to_string()));
+    match socket.read_message()? {
+        Message::Close(Some(close)) => assert_eq!(close.code, CloseCode::Normal),
+        other => panic!("Expected a close frame but got {:?}", other),
+    }
+
+    Ok(())
+}
+
+/// Plain HTTP requests to a WebSocket route are told to upgrade.
+#[test]
+fn websocket_requires_upgrade() -> Result<(), Error> {
+    let dh = DummyhttpProcess::new(vec!["-q", "--config", "tests/data/websocket.yaml"])?;
+
+    let resp = reqwest::blocking::get(format!("{}/echo", dh.url))?;
+    assert_eq!(resp.status(), StatusCode::UPGRADE_REQUIRED);
+
+    Ok(())
+}
+
+/// Frames in both directions are logged.
+#[test]
+fn websocket_logs_frames() -> Result<(), Error> {
+    let mut dh = DummyhttpProcess::new(vec![
+        "--config",
+        "tests/data/websocket.yaml",
+        "--log-format",
+        "json",
+    ])?;
+
+    let (mut socket, _) = tungstenite::connect(ws_url(&dh, "/echo"))?;
+    socket.write_message(Message::Text("logged".to_string()))?;
+    socket.read_message()?;
+    socket.close(None)?;
+
+    dh.child.kill()?;
+    let mut output = String::new();
+    dh.child
+        .stdout
+        .as_mut()
+        .unwrap()
+        .read_to_string(&mut output)?;
+    let frames = output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|record| record.get("frame").is_some())
+        .collect::<Vec<_>>();
+
+    assert_eq!(frames[0]["direction"], "received");
+    assert_eq!(frames[0]["frame"], "text");
+    assert_eq!(frames[0]["data"], "logged");
+    assert_eq!(frames[0]["uri"], "/echo");
+    assert_eq!(frames[1]["direction"], "sent");
+    assert_eq!(frames[1]["data"], "logged");
+
+    Ok(())
+}
+
+/// WebSocket scripts are validated on startup.
+#[test]
+fn websocket_invalid_message() -> Result<(), Error> {
+    Command::cargo_bin("dummyhttp")?
+        .args(["--config", "tests/data/invalid_websocket.yaml"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "WebSocket messages need exactly one of text or binary",
+        ));
+
+    Ok(())
+}